@@ -0,0 +1,189 @@
+use serde::{Serialize, Deserialize};
+
+pub const MINUTES_PER_DAY: u32 = 24 * 60;
+
+// Game time, advanced once per simulation tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldClock {
+    day: u32,
+    // minutes into the current day, fractional so slow clocks still move
+    minute: f64,
+    pub minutes_per_tick: f64,
+}
+
+impl Default for WorldClock {
+    fn default() -> Self {
+        // a game minute every second at 60 ticks per second
+        WorldClock::new(12 * 60, 1.0 / 60.0)
+    }
+}
+
+impl WorldClock {
+    pub fn new(minute_of_day: u32, minutes_per_tick: f64) -> Self {
+        WorldClock {
+            day: minute_of_day / MINUTES_PER_DAY,
+            minute: (minute_of_day % MINUTES_PER_DAY) as f64,
+            minutes_per_tick,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.advance(1);
+    }
+
+    pub fn advance(&mut self, ticks: u32) {
+        self.minute += self.minutes_per_tick * ticks as f64;
+        while self.minute >= MINUTES_PER_DAY as f64 {
+            self.minute -= MINUTES_PER_DAY as f64;
+            self.day += 1;
+        }
+    }
+
+    pub fn day(&self) -> u32 {
+        self.day
+    }
+
+    pub fn minute_of_day(&self) -> u32 {
+        self.minute as u32
+    }
+
+    pub fn hour(&self) -> u32 {
+        self.minute_of_day() / 60
+    }
+}
+
+// A color multiplier keyed by minute of day, interpolated linearly and wrapping at midnight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grading {
+    keys: Vec<(u32, [f32; 3])>,
+}
+
+impl Default for Grading {
+    fn default() -> Self {
+        Grading::new(vec![
+            (0, [0.35, 0.40, 0.65]),
+            (5 * 60, [0.40, 0.45, 0.70]),
+            (7 * 60, [1.00, 0.85, 0.75]),
+            (9 * 60, [1.00, 1.00, 1.00]),
+            (17 * 60, [1.00, 1.00, 1.00]),
+            (19 * 60, [1.00, 0.70, 0.55]),
+            (21 * 60, [0.45, 0.45, 0.75]),
+        ])
+    }
+}
+
+impl Grading {
+    pub fn new(mut keys: Vec<(u32, [f32; 3])>) -> Self {
+        keys.iter_mut().for_each(|k| k.0 %= MINUTES_PER_DAY);
+        keys.sort_by_key(|k| k.0);
+        Grading { keys }
+    }
+
+    pub fn sample(&self, minute: u32) -> [f32; 3] {
+        let minute = minute % MINUTES_PER_DAY;
+
+        match self.keys.len() {
+            0 => return [1.0, 1.0, 1.0],
+            1 => return self.keys[0].1,
+            _ => {}
+        }
+
+        // the last key at or before this minute, wrapping around to yesterday's last key
+        let next = self.keys.iter().position(|k| k.0 > minute).unwrap_or(0);
+        let prev = if next == 0 { self.keys.len() - 1 } else { next - 1 };
+
+        let (m0, c0) = self.keys[prev];
+        let (m1, c1) = self.keys[next];
+
+        let span = (m1 + MINUTES_PER_DAY - m0) % MINUTES_PER_DAY;
+        let into = (minute + MINUTES_PER_DAY - m0) % MINUTES_PER_DAY;
+        let t = if span == 0 { 0.0 } else { into as f32 / span as f32 };
+
+        [
+            c0[0] + (c1[0] - c0[0]) * t,
+            c0[1] + (c1[1] - c0[1]) * t,
+            c0[2] + (c1[2] - c0[2]) * t,
+        ]
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Weather {
+    Clear,
+    Rain,
+    Fog,
+    Sandstorm,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather::Clear
+    }
+}
+
+// What the renderer needs to know to draw the tint and weather overlays.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ambient {
+    pub tint: [f32; 3],
+    pub weather: Weather,
+}
+
+impl Default for Ambient {
+    fn default() -> Self {
+        Ambient { tint: [1.0, 1.0, 1.0], weather: Weather::Clear }
+    }
+}
+
+impl Ambient {
+    // indoor maps ignore the time of day and the weather outside
+    pub fn new(clock: &WorldClock, grading: &Grading, weather: Weather, indoor: bool) -> Self {
+        if indoor {
+            return Ambient::default();
+        }
+
+        Ambient {
+            tint: grading.sample(clock.minute_of_day()),
+            weather,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_wraps_days() {
+        let mut clock = WorldClock::new(23 * 60 + 59, 1.0);
+        clock.tick();
+        assert_eq!((clock.day(), clock.minute_of_day()), (1, 0));
+
+        let mut slow = WorldClock::new(0, 0.25);
+        slow.advance(7);
+        assert_eq!(slow.minute_of_day(), 1);
+    }
+
+    #[test]
+    fn grading_interpolates_and_wraps() {
+        let grading = Grading::new(vec![(6 * 60, [1.0, 1.0, 1.0]), (18 * 60, [0.0, 0.0, 0.0])]);
+        assert_eq!(grading.sample(6 * 60), [1.0, 1.0, 1.0]);
+        assert_eq!(grading.sample(12 * 60), [0.5, 0.5, 0.5]);
+        // halfway through the night, from 18:00 back around to 06:00
+        assert_eq!(grading.sample(0), [0.5, 0.5, 0.5]);
+        assert_eq!(grading.sample(3 * 60), [0.75, 0.75, 0.75]);
+    }
+
+    #[test]
+    fn indoor_opts_out() {
+        let night = WorldClock::new(0, 1.0);
+        let grading = Grading::default();
+
+        assert_eq!(Ambient::new(&night, &grading, Weather::Rain, true), Ambient::default());
+
+        let outside = Ambient::new(&night, &grading, Weather::Rain, false);
+        assert_eq!(outside.weather, Weather::Rain);
+        assert!(outside.tint[0] < 1.0);
+    }
+}
@@ -1,6 +1,11 @@
 use serde::{Serialize, Deserialize};
 use std::ops::{Range, Sub, Add, Deref, DerefMut};
 use std::rc::Rc;
+use crate::warp::Warp;
+use crate::encounter::Encounters;
+use crate::npc::NpcDef;
+use crate::script::Trigger;
+use crate::ambient::Weather;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Area {
@@ -12,6 +17,25 @@ pub struct Area {
     // map properties
     tilemap: TileMap,
     pub name: String, // must be unique
+
+    // display name, music, weather...
+    #[serde(flatten)]
+    pub info: AreaInfo,
+
+    // doors, stairs, cave entrances...
+    #[serde(default)]
+    pub warps: Vec<Warp>,
+
+    // wild encounters per terrain
+    #[serde(default)]
+    pub encounters: Encounters,
+
+    #[serde(default)]
+    pub npcs: Vec<NpcDef>,
+
+    // signs, cutscenes... scripts are referenced by id
+    #[serde(default)]
+    pub triggers: Vec<Trigger>,
 }
 
 impl Deref for Area{
@@ -79,7 +103,12 @@ impl Area {
             east: None,
             west: None,
             tilemap: TileMap::from(vec![]),
-            name: "".to_string()
+            name: "".to_string(),
+            info: AreaInfo::default(),
+            warps: vec![],
+            encounters: Encounters::default(),
+            npcs: vec![],
+            triggers: vec![],
         }
     }
 
@@ -113,12 +142,46 @@ impl Area {
         }
     }
 
+    // what the player sees, e.g. in the location popup
+    pub fn display_name(&self) -> &str {
+        self.info.display_name.as_ref().unwrap_or(&self.name)
+    }
+
     pub fn set_tile(&mut self, x: usize, y: usize, value: u32) {
         let index = y*self.width + x;
         self.map[index] = value;
     }
 
+    pub fn neighbor(&self, dir: Direction) -> Option<&NeighborArea> {
+        match dir {
+            Direction::North => self.north.as_ref(),
+            Direction::South => self.south.as_ref(),
+            Direction::East => self.east.as_ref(),
+            Direction::West => self.west.as_ref(),
+        }
+    }
+
+    // where the neighbor's (0, 0) sits in this area's coordinates
+    pub fn neighbor_origin(&self, dir: Direction, neighbor: &Area) -> Option<(i32, i32)> {
+        let offset = self.neighbor(dir)?.offset;
+        Some(match dir {
+            Direction::North => (offset, -(neighbor.height as i32)),
+            Direction::South => (offset, self.height as i32),
+            Direction::East => (self.width as i32, offset),
+            Direction::West => (-(neighbor.width as i32), offset),
+        })
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height
+    }
+
     pub fn join_maps(&self, north: Option<&Self>, south: Option<&Self>, east: Option<&Self>, west: Option<&Self>) -> Self {
+        self.join_maps_at(north, south, east, west).0
+    }
+
+    // same as join_maps, but also returns where this area's (0, 0) ended up in the joined map
+    pub fn join_maps_at(&self, north: Option<&Self>, south: Option<&Self>, east: Option<&Self>, west: Option<&Self>) -> (Self, (i32, i32)) {
         // TODO: create lib for integral cartesian bullshit
         let center_origin = Point::new(0, 0);
         let center_end = center_origin + Point::new(self.width as i32, self.height as i32);
@@ -216,21 +279,129 @@ impl Area {
             }
         }
 
-        Area {
+        let joined = Area {
             tilemap: TileMap {
                 width: area_size.x as usize,
                 height: area_size.y as usize,
                 map
             },
             ..Area::default()
-        }
+        };
+
+        (joined, (center_origin.x, center_origin.y))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MapType {
+    Outdoor,
+    Indoor,
+    // dark until flash is used, and escape ropes work
+    Cave,
+}
+
+impl MapType {
+    // the time of day and the weather only show outside
+    pub fn is_outside(self) -> bool {
+        self == MapType::Outdoor
+    }
+
+    pub fn is_dark(self) -> bool {
+        self == MapType::Cave
+    }
+
+    pub fn allows_escape_rope(self) -> bool {
+        self == MapType::Cave
+    }
+}
+
+// Everything about an area besides its map and what's in it. It's all optional,
+// so areas written before any of it existed still load.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct AreaInfo {
+    // falls back to the area's name
+    #[serde(default)]
+    pub display_name: Option<String>,
+
+    // a track in res/music, which keeps going between areas that share it
+    #[serde(default)]
+    pub music: Option<String>,
+
+    // what it's like when the player walks in
+    #[serde(default)]
+    pub weather: Option<Weather>,
+
+    #[serde(default)]
+    pub map_type: Option<MapType>,
+
+    // the town map section this area is part of
+    #[serde(default)]
+    pub region: Option<String>,
+
+    // both default to what the map type allows
+    #[serde(default)]
+    pub bike: Option<bool>,
+    #[serde(default)]
+    pub fly: Option<bool>,
+}
+
+impl AreaInfo {
+    pub fn map_type(&self) -> MapType {
+        self.map_type.unwrap_or(MapType::Outdoor)
+    }
+
+    // no weather indoors, whatever the area says
+    pub fn weather(&self) -> Weather {
+        if self.map_type().is_outside() { self.weather.unwrap_or_default() } else { Weather::Clear }
+    }
+
+    pub fn can_bike(&self) -> bool {
+        self.bike.unwrap_or(self.map_type() != MapType::Indoor)
+    }
+
+    // only out under the sky
+    pub fn can_fly(&self) -> bool {
+        self.fly.unwrap_or(self.map_type().is_outside())
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-struct NeighborArea {
-    offset: i32,
-    area: String
+pub struct NeighborArea {
+    pub offset: i32,
+    pub area: String
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::North, Direction::South, Direction::East, Direction::West];
+
+    // one tile in this direction, y grows downwards
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::South => (0, 1),
+            Direction::East => (1, 0),
+            Direction::West => (-1, 0),
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::South => Direction::North,
+            Direction::East => Direction::West,
+            Direction::West => Direction::East,
+        }
+    }
 }
 
 
@@ -470,6 +641,53 @@ mod tests {
 
         assert_eq!(joined.tilemap, expected.tilemap);
     }
+
+    #[test]
+    fn metadata_defaults() {
+        let house = prep_area(&mut new_areas_dict(), r#"{
+            "tilemap": { "width": 1, "height": 1, "map": [ 1 ] },
+            "name": "house_1f",
+            "map_type": "indoor"
+        }"#);
+        assert_eq!(house.display_name(), "house_1f");
+        assert_eq!(house.info.map_type(), MapType::Indoor);
+        assert!(!house.info.can_bike() && !house.info.can_fly());
+
+        let plain = prep_area(&mut new_areas_dict(), r#"{
+            "tilemap": { "width": 1, "height": 1, "map": [ 1 ] },
+            "name": "route_1"
+        }"#);
+        assert_eq!(plain.info.map_type(), MapType::Outdoor);
+        assert_eq!(plain.info.weather(), Weather::Clear);
+        assert!(plain.info.can_bike() && plain.info.can_fly());
+    }
+
+    #[test]
+    fn metadata() {
+        let cave = prep_area(&mut new_areas_dict(), r#"{
+            "tilemap": { "width": 1, "height": 1, "map": [ 1 ] },
+            "name": "mt_moon_b1f",
+            "display_name": "MT. MOON",
+            "music": "mt_moon",
+            "weather": "fog",
+            "map_type": "cave",
+            "region": "route_3",
+            "bike": false
+        }"#);
+        assert_eq!(cave.display_name(), "MT. MOON");
+        assert_eq!(cave.info.music.as_ref().unwrap(), "mt_moon");
+        assert_eq!(cave.info.map_type(), MapType::Cave);
+        assert!(cave.info.map_type().is_dark() && cave.info.map_type().allows_escape_rope());
+        assert_eq!(cave.info.weather(), Weather::Clear);
+        assert_eq!(cave.info.region.as_ref().unwrap(), "route_3");
+        assert!(!cave.info.can_bike() && !cave.info.can_fly());
+
+        // survives a round trip
+        let json = serde_json::to_string(&*cave).unwrap();
+        let again: Area = serde_json::from_str(&json).unwrap();
+        assert_eq!(again.info.map_type(), MapType::Cave);
+        assert_eq!(again.display_name(), "MT. MOON");
+    }
 }
 
 
@@ -488,7 +706,7 @@ mod tests {
 
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 struct Point {
     pub x: i32,
     pub y: i32,
@@ -0,0 +1,820 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::settings::Volumes;
+
+// how many sound effects can play at once
+pub const MAX_SFX: usize = 8;
+// seconds it takes for one area's music to give way to the next
+pub const CROSSFADE: f32 = 1.0;
+// music is turned down this much while a cry plays
+const CRY_DUCK: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SoundError {
+    Io(String),
+    Decode(String),
+    // not .ogg or .wav
+    Format,
+}
+
+impl fmt::Display for SoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SoundError::Io(e) => write!(f, "couldn't read sound: {}", e),
+            SoundError::Decode(e) => write!(f, "couldn't decode sound: {}", e),
+            SoundError::Format => write!(f, "sounds have to be .ogg or .wav"),
+        }
+    }
+}
+
+// A decoded sound, kept as interleaved stereo at whatever rate it was made at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    rate: u32,
+    samples: Vec<f32>,
+    // music jumps back to loop_start (in frames) at loop_end, or the end if there's none
+    pub loop_start: usize,
+    pub loop_end: Option<usize>,
+}
+
+// mono plays on both sides, and anything past the first two channels is dropped
+fn stereo(channels: usize, samples: &[f32]) -> Vec<f32> {
+    match channels {
+        0 => vec![],
+        1 => samples.iter().flat_map(|&s| std::iter::repeat(s).take(2)).collect(),
+        _ => samples.chunks_exact(channels).flat_map(|c| c[..2].iter().cloned()).collect(),
+    }
+}
+
+// Loop points come from LOOPSTART plus LOOPLENGTH or LOOPEND comments, in frames,
+// the way most game music rips have them.
+fn loop_tags(comments: &[(String, String)]) -> (usize, Option<usize>) {
+    let tag = |name: &str| comments.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok());
+    match tag("LOOPSTART") {
+        Some(start) => (start, tag("LOOPLENGTH").map(|length| start + length).or_else(|| tag("LOOPEND"))),
+        None => (0, None),
+    }
+}
+
+impl Sound {
+    pub fn from_samples(rate: u32, channels: usize, samples: &[f32]) -> Sound {
+        Sound { rate: rate.max(1), samples: stereo(channels, samples), loop_start: 0, loop_end: None }
+    }
+
+    pub fn from_wav(bytes: &[u8]) -> Result<Sound, SoundError> {
+        let mut reader = hound::WavReader::new(Cursor::new(bytes)).map_err(|e| SoundError::Decode(e.to_string()))?;
+        let spec = reader.spec();
+        let samples: Result<Vec<f32>, _> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>().collect(),
+            hound::SampleFormat::Int => {
+                let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+                reader.samples::<i32>().map(|s| s.map(|s| s as f32 / scale)).collect()
+            }
+        };
+        let samples = samples.map_err(|e| SoundError::Decode(e.to_string()))?;
+        Ok(Sound::from_samples(spec.sample_rate, spec.channels as usize, &samples))
+    }
+
+    // all of it up front, which is fine for sound effects. Music wants a Track.
+    pub fn from_ogg(bytes: &[u8]) -> Result<Sound, SoundError> {
+        let decode = |e: lewton::VorbisError| SoundError::Decode(e.to_string());
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(Cursor::new(bytes)).map_err(decode)?;
+
+        let mut samples = vec![];
+        while let Some(packet) = reader.read_dec_packet_itl().map_err(decode)? {
+            samples.extend(packet.iter().map(|&s| s as f32 / 32768.0));
+        }
+
+        let header = &reader.ident_hdr;
+        let mut sound = Sound::from_samples(header.audio_sample_rate, header.audio_channels as usize, &samples);
+
+        let (start, end) = loop_tags(&reader.comment_hdr.comment_list);
+        sound.loop_start = start;
+        sound.loop_end = end;
+        Ok(sound)
+    }
+
+    pub fn load(path: &Path) -> Result<Sound, SoundError> {
+        let bytes = std::fs::read(path).map_err(|e| SoundError::Io(e.to_string()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ogg") => Sound::from_ogg(&bytes),
+            Some("wav") => Sound::from_wav(&bytes),
+            _ => Err(SoundError::Format),
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / 2
+    }
+
+    fn frame(&self, i: usize) -> (f32, f32) {
+        (self.samples[i * 2], self.samples[i * 2 + 1])
+    }
+
+    // frame `i` and the one after it, for blending in between
+    fn pair(&self, i: usize) -> Option<((f32, f32), (f32, f32))> {
+        let frames = self.frames();
+        if i >= frames {
+            return None;
+        }
+        let a = self.frame(i);
+        Some((a, if i + 1 < frames { self.frame(i + 1) } else { a }))
+    }
+}
+
+// Where a Stream gets its samples from.
+pub trait Decoder: Send {
+    // the next bit of interleaved stereo, None at the end
+    fn packet(&mut self) -> Option<Vec<f32>>;
+    // Goes back to somewhere at or before `frame`, and returns where that was.
+    fn seek(&mut self, frame: usize) -> Option<usize>;
+}
+
+// so every stream of a track can share the one copy of it
+struct Shared(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for Shared {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+type OggReader = lewton::inside_ogg::OggStreamReader<Cursor<Shared>>;
+
+fn open_ogg(bytes: &Arc<Vec<u8>>) -> Result<OggReader, SoundError> {
+    OggReader::new(Cursor::new(Shared(bytes.clone()))).map_err(|e| SoundError::Decode(e.to_string()))
+}
+
+struct OggDecoder {
+    bytes: Arc<Vec<u8>>,
+    reader: OggReader,
+    // decoded while finding out where a seek landed, and not handed out yet
+    pending: VecDeque<Vec<f32>>,
+}
+
+impl OggDecoder {
+    fn read(&mut self) -> Option<Vec<f32>> {
+        match self.reader.read_dec_packet_itl() {
+            Ok(Some(packet)) => {
+                let samples: Vec<f32> = packet.iter().map(|&s| s as f32 / 32768.0).collect();
+                Some(stereo(self.reader.ident_hdr.audio_channels as usize, &samples))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                println!("couldn't decode music: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl Decoder for OggDecoder {
+    fn packet(&mut self) -> Option<Vec<f32>> {
+        match self.pending.pop_front() {
+            Some(packet) => Some(packet),
+            None => self.read(),
+        }
+    }
+
+    fn seek(&mut self, frame: usize) -> Option<usize> {
+        self.pending.clear();
+        if frame > 0 && self.reader.seek_absgp_pg(frame as u64).is_ok() {
+            // lewton only knows where it is again at the end of a page, so count back from there
+            let mut frames = 0;
+            while let Some(packet) = self.read() {
+                frames += packet.len() / 2;
+                self.pending.push_back(packet);
+                if let Some(end) = self.reader.get_last_absgp() {
+                    match (end as usize).checked_sub(frames) {
+                        Some(landed) if landed <= frame => return Some(landed),
+                        _ => break,
+                    }
+                }
+            }
+        }
+
+        // from the top then
+        self.pending.clear();
+        self.reader = open_ogg(&self.bytes).ok()?;
+        Some(0)
+    }
+}
+
+// Music that's decoded a packet at a time as it plays, instead of all up front.
+// Looping back seeks the decoder.
+pub struct Stream {
+    decoder: Box<dyn Decoder>,
+    rate: u32,
+    loop_start: usize,
+    loop_end: Option<usize>,
+    // interleaved stereo, starting at frame `base`
+    buffer: VecDeque<f32>,
+    base: usize,
+}
+
+impl fmt::Debug for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Stream")
+            .field("rate", &self.rate)
+            .field("loop_start", &self.loop_start)
+            .field("loop_end", &self.loop_end)
+            .field("base", &self.base)
+            .finish()
+    }
+}
+
+impl Stream {
+    pub fn new(decoder: Box<dyn Decoder>, rate: u32, loop_start: usize, loop_end: Option<usize>) -> Stream {
+        Stream { decoder, rate: rate.max(1), loop_start, loop_end, buffer: VecDeque::new(), base: 0 }
+    }
+
+    fn pair(&mut self, i: usize) -> Option<((f32, f32), (f32, f32))> {
+        if i < self.base {
+            self.buffer.clear();
+            self.base = self.decoder.seek(i).filter(|&landed| landed <= i)?;
+        }
+
+        // everything before `i` has been played
+        let played = ((i - self.base) * 2).min(self.buffer.len());
+        self.buffer.drain(..played);
+        self.base += played / 2;
+
+        let at = (i - self.base) * 2;
+        while self.buffer.len() < at + 4 {
+            match self.decoder.packet() {
+                Some(packet) => self.buffer.extend(packet),
+                None => break,
+            }
+        }
+
+        let b = &self.buffer;
+        if at + 1 >= b.len() {
+            return None;
+        }
+        let first = (b[at], b[at + 1]);
+        Some((first, if at + 3 < b.len() { (b[at + 2], b[at + 3]) } else { first }))
+    }
+}
+
+// A piece of music. Oggs stay compressed and are only decoded while they play, so
+// switching tracks doesn't have to wait for a whole song to decode.
+#[derive(Debug)]
+pub enum Track {
+    Ogg(Arc<Vec<u8>>),
+    // already decoded, like a .wav
+    Sound(Arc<Sound>),
+}
+
+impl Track {
+    pub fn from_ogg(bytes: Vec<u8>) -> Result<Track, SoundError> {
+        let bytes = Arc::new(bytes);
+        // reads the headers, so broken files are caught now instead of when they play
+        open_ogg(&bytes)?;
+        Ok(Track::Ogg(bytes))
+    }
+
+    fn source(&self) -> Result<Source, SoundError> {
+        match self {
+            Track::Sound(sound) => Ok(Source::Sound(sound.clone())),
+            Track::Ogg(bytes) => {
+                let reader = open_ogg(bytes)?;
+                let rate = reader.ident_hdr.audio_sample_rate;
+                let (start, end) = loop_tags(&reader.comment_hdr.comment_list);
+                let decoder = OggDecoder { bytes: bytes.clone(), reader, pending: VecDeque::new() };
+                Ok(Source::Stream(Box::new(Stream::new(Box::new(decoder), rate, start, end))))
+            }
+        }
+    }
+}
+
+// Things a SoundBank can load.
+pub trait Load: Sized {
+    fn load(path: &Path) -> Result<Self, SoundError>;
+}
+
+impl Load for Sound {
+    fn load(path: &Path) -> Result<Sound, SoundError> {
+        Sound::load(path)
+    }
+}
+
+impl Load for Track {
+    fn load(path: &Path) -> Result<Track, SoundError> {
+        let bytes = std::fs::read(path).map_err(|e| SoundError::Io(e.to_string()))?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("ogg") => Track::from_ogg(bytes),
+            Some("wav") => Ok(Track::Sound(Arc::new(Sound::from_wav(&bytes)?))),
+            _ => Err(SoundError::Format),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Source {
+    Sound(Arc<Sound>),
+    Stream(Box<Stream>),
+}
+
+impl Source {
+    fn rate(&self) -> u32 {
+        match self {
+            Source::Sound(sound) => sound.rate,
+            Source::Stream(stream) => stream.rate,
+        }
+    }
+
+    fn loop_points(&self) -> (usize, Option<usize>) {
+        match self {
+            Source::Sound(sound) => (sound.loop_start, sound.loop_end),
+            Source::Stream(stream) => (stream.loop_start, stream.loop_end),
+        }
+    }
+
+    fn pair(&mut self, i: usize) -> Option<((f32, f32), (f32, f32))> {
+        match self {
+            Source::Sound(sound) => sound.pair(i),
+            Source::Stream(stream) => stream.pair(i),
+        }
+    }
+}
+
+// One sound playing on a channel.
+#[derive(Debug)]
+struct Voice {
+    source: Source,
+    // in the sound's frames, which don't have to line up with the mixer's
+    pos: f64,
+    looping: bool,
+    gain: f32,
+    // added to gain every frame, for fades
+    fade: f32,
+    done: bool,
+}
+
+impl Voice {
+    fn new(source: Source, looping: bool) -> Voice {
+        Voice { source, pos: 0.0, looping, gain: 1.0, fade: 0.0, done: false }
+    }
+
+    fn faded_out(&self) -> bool {
+        self.fade < 0.0 && self.gain <= 0.0
+    }
+
+    // None once it's done
+    fn next(&mut self, rate: u32) -> Option<(f32, f32)> {
+        let (start, end) = self.source.loop_points();
+        match end {
+            Some(end) if self.looping && self.pos >= end as f64 => {
+                if start >= end {
+                    return None;
+                }
+                self.pos = start as f64 + (self.pos - end as f64) % (end - start) as f64;
+            }
+            _ => {}
+        }
+
+        let i = self.pos as usize;
+        let (a, b) = match self.source.pair(i) {
+            Some(pair) => pair,
+            // ran out before the loop end, or there wasn't one
+            None if self.looping && i > start => {
+                self.pos = start as f64 + self.pos.fract();
+                self.source.pair(start)?
+            }
+            None => return None,
+        };
+
+        // linear in between frames, since the rates don't have to match
+        let t = self.pos.fract() as f32;
+        let gain = self.gain;
+
+        self.pos += self.source.rate() as f64 / rate as f64;
+        self.gain = (self.gain + self.fade).max(0.0).min(1.0);
+
+        Some(((a.0 + (b.0 - a.0) * t) * gain, (a.1 + (b.1 - a.1) * t) * gain))
+    }
+}
+
+// the next frame on a channel, which is emptied once it's done
+fn play(voice: &mut Option<Voice>, rate: u32) -> (f32, f32) {
+    match voice.as_mut().and_then(|v| v.next(rate)) {
+        Some(sample) => sample,
+        None => {
+            *voice = None;
+            (0.0, 0.0)
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bus {
+    Music,
+    Sfx,
+    Cries,
+}
+
+// Mixes everything that's playing into interleaved stereo at `rate`. It doesn't know
+// about any sound device, whoever owns it pulls samples out with render.
+#[derive(Debug)]
+pub struct Mixer {
+    rate: u32,
+    volumes: Volumes,
+    // what play_music was last asked for
+    track: Option<Arc<Track>>,
+    music: Option<Voice>,
+    // the last track, on its way out
+    old_music: Option<Voice>,
+    // with their priorities, oldest first
+    sfx: Vec<(u8, Voice)>,
+    cry: Option<Voice>,
+}
+
+impl Mixer {
+    pub fn new(rate: u32) -> Self {
+        Mixer {
+            rate: rate.max(1),
+            volumes: Volumes::default(),
+            track: None,
+            music: None,
+            old_music: None,
+            sfx: vec![],
+            cry: None,
+        }
+    }
+
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn set_volumes(&mut self, volumes: Volumes) {
+        self.volumes = volumes;
+    }
+
+    // 0 to 1, master volume included
+    pub fn volume(&self, bus: Bus) -> f32 {
+        let bus = match bus {
+            Bus::Music => self.volumes.music,
+            Bus::Sfx => self.volumes.sfx,
+            Bus::Cries => self.volumes.cries,
+        };
+        self.volumes.master.min(100) as f32 / 100.0 * bus.min(100) as f32 / 100.0
+    }
+
+    pub fn music(&self) -> Option<&Arc<Track>> {
+        self.track.as_ref()
+    }
+
+    // Crossfades to another track, or to silence, over `fade` seconds. Asking for
+    // the track that's already on does nothing, so it keeps going between areas.
+    pub fn play_music(&mut self, track: Option<Arc<Track>>, fade: f32) {
+        let same = match (&self.track, &track) {
+            (Some(playing), Some(track)) => Arc::ptr_eq(playing, track),
+            (None, None) => true,
+            _ => false,
+        };
+        if same {
+            return;
+        }
+
+        let source = track.as_ref().and_then(|t| match t.source() {
+            Ok(source) => Some(source),
+            Err(e) => {
+                println!("{}", e);
+                None
+            }
+        });
+        self.track = track;
+
+        let frames = fade * self.rate as f32;
+        if frames < 1.0 {
+            self.old_music = None;
+            self.music = source.map(|s| Voice::new(s, true));
+            return;
+        }
+
+        // anything that was still fading out gets cut off
+        self.old_music = self.music.take().map(|mut v| {
+            v.fade = -1.0 / frames;
+            v
+        });
+        self.music = source.map(|s| {
+            let mut v = Voice::new(s, true);
+            v.gain = 0.0;
+            v.fade = 1.0 / frames;
+            v
+        });
+    }
+
+    // Returns false if every channel is busy with something more important. Otherwise
+    // the least important sound gets cut off, the oldest one if there's a tie.
+    pub fn play_sfx(&mut self, sound: Arc<Sound>, priority: u8) -> bool {
+        if self.sfx.len() >= MAX_SFX {
+            let (i, lowest) = self.sfx.iter().enumerate()
+                .min_by_key(|(_, (p, _))| *p)
+                .map(|(i, (p, _))| (i, *p))
+                .unwrap();
+            if lowest > priority {
+                return false;
+            }
+            self.sfx.remove(i);
+        }
+
+        self.sfx.push((priority, Voice::new(Source::Sound(sound), false)));
+        true
+    }
+
+    pub fn stop_sfx(&mut self) {
+        self.sfx.clear();
+    }
+
+    // a new cry cuts off the last one
+    pub fn play_cry(&mut self, sound: Arc<Sound>) {
+        self.cry = Some(Voice::new(Source::Sound(sound), false));
+    }
+
+    // Fills `out` with interleaved stereo, overwriting whatever was in it.
+    pub fn render(&mut self, out: &mut [f32]) {
+        let rate = self.rate;
+        let (sfx_volume, cry_volume) = (self.volume(Bus::Sfx), self.volume(Bus::Cries));
+
+        for frame in out.chunks_exact_mut(2) {
+            let duck = if self.cry.is_some() { CRY_DUCK } else { 1.0 };
+            let music_volume = self.volume(Bus::Music) * duck;
+            let (mut l, mut r) = (0.0, 0.0);
+            let mut add = |sample: (f32, f32), volume: f32| {
+                l += sample.0 * volume;
+                r += sample.1 * volume;
+            };
+
+            add(play(&mut self.music, rate), music_volume);
+            add(play(&mut self.old_music, rate), music_volume);
+            add(play(&mut self.cry, rate), cry_volume);
+            if self.old_music.as_ref().map_or(false, |v| v.faded_out()) {
+                self.old_music = None;
+            }
+
+            let mut finished = false;
+            for (_, voice) in &mut self.sfx {
+                match voice.next(rate) {
+                    Some(sample) => add(sample, sfx_volume),
+                    None => {
+                        voice.done = true;
+                        finished = true;
+                    }
+                }
+            }
+            if finished {
+                self.sfx.retain(|(_, v)| !v.done);
+            }
+
+            frame[0] = l.max(-1.0).min(1.0);
+            frame[1] = r.max(-1.0).min(1.0);
+        }
+    }
+}
+
+// Sounds (or tracks) loaded by name from a folder, e.g. "route1" -> res/music/route1.ogg,
+// and kept around after that. Missing ones are printed once and then just don't play.
+#[derive(Debug)]
+pub struct SoundBank<T = Sound> {
+    dir: PathBuf,
+    sounds: HashMap<String, Option<Arc<T>>>,
+}
+
+impl<T: Load> SoundBank<T> {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        SoundBank { dir: dir.into(), sounds: HashMap::new() }
+    }
+
+    pub fn insert(&mut self, name: &str, sound: T) {
+        self.sounds.insert(name.to_string(), Some(Arc::new(sound)));
+    }
+
+    pub fn get(&mut self, name: &str) -> Option<Arc<T>> {
+        let dir = &self.dir;
+        self.sounds.entry(name.to_string()).or_insert_with(|| {
+            let path = ["ogg", "wav"].iter()
+                .map(|ext| dir.join(format!("{}.{}", name, ext)))
+                .find(|path| path.exists());
+
+            match path.map(|path| T::load(&path)) {
+                Some(Ok(sound)) => Some(Arc::new(sound)),
+                Some(Err(e)) => {
+                    println!("{}: {}", name, e);
+                    None
+                }
+                None => {
+                    println!("no sound called {} in {}", name, dir.display());
+                    None
+                }
+            }
+        }).clone()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a sound that's `value` on both sides the whole way through
+    fn flat(rate: u32, frames: usize, value: f32) -> Arc<Sound> {
+        Arc::new(Sound::from_samples(rate, 1, &vec![value; frames]))
+    }
+
+    fn track(sound: Arc<Sound>) -> Arc<Track> {
+        Arc::new(Track::Sound(sound))
+    }
+
+    fn render(mixer: &mut Mixer, frames: usize) -> Vec<f32> {
+        let mut out = vec![0.0; frames * 2];
+        mixer.render(&mut out);
+        out
+    }
+
+    fn full_volume() -> Volumes {
+        Volumes { master: 100, music: 100, sfx: 100, cries: 100 }
+    }
+
+    #[test]
+    fn channels_to_stereo() {
+        let mono = Sound::from_samples(100, 1, &[0.5, -0.5]);
+        assert_eq!(mono.frames(), 2);
+        assert_eq!(mono.frame(1), (-0.5, -0.5));
+
+        let quad = Sound::from_samples(100, 4, &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8]);
+        assert_eq!(quad.frame(1), (0.5, 0.6));
+    }
+
+    #[test]
+    fn wav_files() {
+        let spec = hound::WavSpec { channels: 2, sample_rate: 22050, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut bytes = Cursor::new(vec![]);
+        {
+            let mut writer = hound::WavWriter::new(&mut bytes, spec).unwrap();
+            for &s in &[16384i16, -16384, 0, 32767] {
+                writer.write_sample(s).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+
+        let sound = Sound::from_wav(bytes.get_ref()).unwrap();
+        assert_eq!((sound.rate(), sound.frames()), (22050, 2));
+        assert_eq!(sound.frame(0), (0.5, -0.5));
+
+        assert!(match Sound::from_wav(b"RIFF nonsense") { Err(SoundError::Decode(_)) => true, _ => false });
+        assert!(match Sound::from_ogg(b"OggS nonsense") { Err(SoundError::Decode(_)) => true, _ => false });
+    }
+
+    #[test]
+    fn music_loops() {
+        let mut track = Sound::from_samples(10, 1, &[0.1, 0.2, 0.3, 0.4, 0.5]);
+        track.loop_start = 1;
+        track.loop_end = Some(4);
+
+        let mut mixer = Mixer::new(10);
+        mixer.set_volumes(full_volume());
+        mixer.play_music(Some(self::track(Arc::new(track))), 0.0);
+
+        let left: Vec<f32> = render(&mut mixer, 8).iter().step_by(2).cloned().collect();
+        assert_eq!(left, vec![0.1, 0.2, 0.3, 0.4, 0.2, 0.3, 0.4, 0.2]);
+    }
+
+    // Hands out a ramp going up by 1/16 a frame in packets of `packet`, and can only seek
+    // to the start of a packet, like lewton and its pages.
+    struct Ramp {
+        frames: usize,
+        packet: usize,
+        at: usize,
+        seeks: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl Decoder for Ramp {
+        fn packet(&mut self) -> Option<Vec<f32>> {
+            if self.at >= self.frames {
+                return None;
+            }
+            let end = (self.at + self.packet).min(self.frames);
+            let packet = (self.at..end).map(|f| f as f32 / 16.0).flat_map(|f| vec![f, -f]).collect();
+            self.at = end;
+            Some(packet)
+        }
+
+        fn seek(&mut self, frame: usize) -> Option<usize> {
+            self.seeks.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.at = frame / self.packet * self.packet;
+            Some(self.at)
+        }
+    }
+
+    #[test]
+    fn streams_loop_by_seeking() {
+        let seeks = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let ramp = Ramp { frames: 10, packet: 3, at: 0, seeks: seeks.clone() };
+        let stream = Stream::new(Box::new(ramp), 10, 4, Some(8));
+
+        let mut mixer = Mixer::new(10);
+        mixer.set_volumes(full_volume());
+        mixer.music = Some(Voice::new(Source::Stream(Box::new(stream)), true));
+
+        let out = render(&mut mixer, 12);
+        let left: Vec<f32> = out.iter().step_by(2).map(|s| s * 16.0).collect();
+        assert_eq!(left, vec![0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(out[15], -7.0 / 16.0);
+        assert_eq!(seeks.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // without a loop end it goes back once the decoder runs dry
+        let ramp = Ramp { frames: 5, packet: 2, at: 0, seeks: seeks.clone() };
+        let stream = Stream::new(Box::new(ramp), 10, 3, None);
+        mixer.music = Some(Voice::new(Source::Stream(Box::new(stream)), true));
+        let left: Vec<f32> = render(&mut mixer, 8).iter().step_by(2).map(|s| s * 16.0).collect();
+        assert_eq!(left, vec![0.0, 1.0, 2.0, 3.0, 4.0, 3.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn resamples_to_the_mixer() {
+        let track = Arc::new(Sound::from_samples(10, 1, &[0.0, 1.0, 0.0]));
+        let mut mixer = Mixer::new(20);
+        mixer.set_volumes(full_volume());
+        mixer.play_sfx(track, 0);
+
+        let left: Vec<f32> = render(&mut mixer, 6).iter().step_by(2).cloned().collect();
+        assert_eq!(left, vec![0.0, 0.5, 1.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn crossfade() {
+        let mut bank = SoundBank::new("nowhere");
+        bank.insert("route1", Track::Sound(flat(100, 1000, 0.5)));
+        bank.insert("town", Track::Sound(flat(100, 1000, -0.5)));
+
+        let mut mixer = Mixer::new(100);
+        mixer.set_volumes(full_volume());
+        mixer.play_music(bank.get("route1"), CROSSFADE);
+        // fading in from nothing
+        let out = render(&mut mixer, 100);
+        assert_eq!(out[0], 0.0);
+        assert!((out[198] - 0.495).abs() < 0.001);
+
+        // the same track again doesn't restart it
+        mixer.play_music(bank.get("route1"), CROSSFADE);
+        assert!((render(&mut mixer, 1)[0] - 0.5).abs() < 0.001);
+
+        mixer.play_music(bank.get("town"), CROSSFADE);
+        let out = render(&mut mixer, 101);
+        // halfway there they cancel out
+        assert!(out[100].abs() < 0.01);
+        assert!((out[200] + 0.5).abs() < 0.001);
+        assert!(mixer.old_music.is_none());
+
+        mixer.play_music(None, 0.0);
+        assert!(mixer.music().is_none());
+        assert_eq!(render(&mut mixer, 1), vec![0.0, 0.0]);
+
+        // missing tracks are silence
+        assert!(bank.get("nope").is_none());
+    }
+
+    #[test]
+    fn sfx_priority() {
+        let mut mixer = Mixer::new(10);
+        mixer.set_volumes(full_volume());
+        for i in 0..MAX_SFX {
+            assert!(mixer.play_sfx(flat(10, 10, 0.01 * i as f32), 5));
+        }
+
+        assert!(!mixer.play_sfx(flat(10, 10, 0.5), 4));
+        // ties cut off the oldest, which was silent anyway
+        assert!(mixer.play_sfx(flat(10, 10, 0.5), 5));
+        assert_eq!(mixer.sfx.len(), MAX_SFX);
+        assert!((render(&mut mixer, 1)[0] - 0.78).abs() < 0.001);
+
+        // finished sounds free up their channel
+        render(&mut mixer, 20);
+        assert!(mixer.sfx.is_empty());
+    }
+
+    #[test]
+    fn buses() {
+        let mut mixer = Mixer::new(10);
+        mixer.set_volumes(Volumes { master: 50, music: 50, sfx: 100, cries: 0 });
+        assert_eq!(mixer.volume(Bus::Music), 0.25);
+
+        mixer.play_music(Some(track(flat(10, 100, 1.0))), 0.0);
+        mixer.play_sfx(flat(10, 2, 0.5), 0);
+        assert_eq!(render(&mut mixer, 1), vec![0.5, 0.5]);
+
+        // a cry ducks the music even when it can't be heard
+        mixer.play_cry(flat(10, 1, 1.0));
+        assert_eq!(render(&mut mixer, 3), vec![0.375, 0.375, 0.125, 0.125, 0.25, 0.25]);
+    }
+}
@@ -0,0 +1,609 @@
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+// one in this many hits is a critical hit
+pub const CRIT_CHANCE: u32 = 24;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    Normal,
+    Fire,
+    Water,
+    Electric,
+    Grass,
+    Ice,
+    Fighting,
+    Poison,
+    Ground,
+    Flying,
+    Psychic,
+    Bug,
+    Rock,
+    Ghost,
+    Dragon,
+    Dark,
+    Steel,
+    Fairy,
+}
+
+impl Type {
+    // (super effective against, not very effective against, no effect on)
+    fn matchups(self) -> (&'static [Type], &'static [Type], &'static [Type]) {
+        use self::Type::*;
+        match self {
+            Normal => (&[], &[Rock, Steel], &[Ghost]),
+            Fire => (&[Grass, Ice, Bug, Steel], &[Fire, Water, Rock, Dragon], &[]),
+            Water => (&[Fire, Ground, Rock], &[Water, Grass, Dragon], &[]),
+            Electric => (&[Water, Flying], &[Electric, Grass, Dragon], &[Ground]),
+            Grass => (&[Water, Ground, Rock], &[Fire, Grass, Poison, Flying, Bug, Dragon, Steel], &[]),
+            Ice => (&[Grass, Ground, Flying, Dragon], &[Fire, Water, Ice, Steel], &[]),
+            Fighting => (&[Normal, Ice, Rock, Dark, Steel], &[Poison, Flying, Psychic, Bug, Fairy], &[Ghost]),
+            Poison => (&[Grass, Fairy], &[Poison, Ground, Rock, Ghost], &[Steel]),
+            Ground => (&[Fire, Electric, Poison, Rock, Steel], &[Grass, Bug], &[Flying]),
+            Flying => (&[Grass, Fighting, Bug], &[Electric, Rock, Steel], &[]),
+            Psychic => (&[Fighting, Poison], &[Psychic, Steel], &[Dark]),
+            Bug => (&[Grass, Psychic, Dark], &[Fire, Fighting, Poison, Flying, Ghost, Steel, Fairy], &[]),
+            Rock => (&[Fire, Ice, Flying, Bug], &[Fighting, Ground, Steel], &[]),
+            Ghost => (&[Psychic, Ghost], &[Dark], &[Normal]),
+            Dragon => (&[Dragon], &[Steel], &[Fairy]),
+            Dark => (&[Psychic, Ghost], &[Fighting, Dark, Fairy], &[]),
+            Steel => (&[Ice, Rock, Fairy], &[Fire, Water, Electric, Steel], &[]),
+            Fairy => (&[Fighting, Dragon, Dark], &[Fire, Poison, Steel], &[]),
+        }
+    }
+
+    // 0, 0.5, 1 or 2
+    pub fn against(self, defender: Type) -> f32 {
+        let (strong, weak, immune) = self.matchups();
+        if immune.contains(&defender) {
+            0.0
+        } else if weak.contains(&defender) {
+            0.5
+        } else if strong.contains(&defender) {
+            2.0
+        } else {
+            1.0
+        }
+    }
+
+    // against every one of the defender's types, so anywhere from 0 to 4
+    pub fn against_all(self, defender: &[Type]) -> f32 {
+        defender.iter().map(|&t| self.against(t)).product()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq)]
+pub struct Stats {
+    pub hp: u32,
+    pub attack: u32,
+    pub defense: u32,
+    pub sp_attack: u32,
+    pub sp_defense: u32,
+    pub speed: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Species {
+    pub name: String,
+    // one or two
+    pub types: Vec<Type>,
+    pub base: Stats,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    // attack against defense
+    Physical,
+    // sp_attack against sp_defense
+    Special,
+    // doesn't do damage
+    Status,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Move {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: Type,
+    pub category: Category,
+    #[serde(default)]
+    pub power: u32,
+    // out of 100, moves without one never miss
+    #[serde(default)]
+    pub accuracy: Option<u32>,
+    pub pp: u32,
+    // higher goes first, whatever the speed
+    #[serde(default)]
+    pub priority: i8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MoveSlot {
+    pub data: Move,
+    pub pp: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Creature {
+    pub name: String,
+    pub level: u8,
+    pub types: Vec<Type>,
+    pub stats: Stats,
+    pub hp: u32,
+    pub moves: Vec<MoveSlot>,
+}
+
+impl Creature {
+    pub fn new(species: &Species, level: u8, moves: &[Move]) -> Self {
+        Creature::with_ivs(species, level, Stats::default(), moves)
+    }
+
+    // Stats from the usual formula, without EVs or natures.
+    pub fn with_ivs(species: &Species, level: u8, ivs: Stats, moves: &[Move]) -> Self {
+        let level = level.max(1).min(100);
+        let l = level as u32;
+        let stat = |base: u32, iv: u32| (2 * base + iv) * l / 100 + 5;
+        let (b, i) = (species.base, ivs);
+
+        let stats = Stats {
+            hp: (2 * b.hp + i.hp) * l / 100 + l + 10,
+            attack: stat(b.attack, i.attack),
+            defense: stat(b.defense, i.defense),
+            sp_attack: stat(b.sp_attack, i.sp_attack),
+            sp_defense: stat(b.sp_defense, i.sp_defense),
+            speed: stat(b.speed, i.speed),
+        };
+
+        Creature {
+            name: species.name.clone(),
+            level,
+            types: species.types.clone(),
+            stats,
+            hp: stats.hp,
+            moves: moves.iter().take(4).map(|m| MoveSlot { data: m.clone(), pp: m.pp }).collect(),
+        }
+    }
+
+    pub fn is_fainted(&self) -> bool {
+        self.hp == 0
+    }
+}
+
+// How much `attacker` hurts `defender` with `m`, before anything random besides the
+// given crit and roll (85 to 100). Returns the damage and the type effectiveness.
+pub fn damage(attacker: &Creature, defender: &Creature, m: &Move, crit: bool, roll: u32) -> (u32, f32) {
+    let effectiveness = m.ty.against_all(&defender.types);
+    let (attack, defense) = match m.category {
+        Category::Physical => (attacker.stats.attack, defender.stats.defense),
+        Category::Special => (attacker.stats.sp_attack, defender.stats.sp_defense),
+        Category::Status => return (0, effectiveness),
+    };
+    if m.power == 0 || effectiveness == 0.0 {
+        return (0, effectiveness);
+    }
+
+    let mut damage = (2 * attacker.level as u32 / 5 + 2) * m.power * attack / defense.max(1) / 50 + 2;
+    if crit {
+        damage = damage * 3 / 2;
+    }
+    damage = damage * roll.max(85).min(100) / 100;
+    if attacker.types.contains(&m.ty) {
+        damage = damage * 3 / 2;
+    }
+    damage = (damage as f32 * effectiveness) as u32;
+
+    (damage.max(1), effectiveness)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Side {
+    Player,
+    Opponent,
+}
+
+impl Side {
+    pub fn other(self) -> Side {
+        match self {
+            Side::Player => Side::Opponent,
+            Side::Opponent => Side::Player,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    // index into the creature's moves
+    Move(usize),
+}
+
+// What happened, in order. Display gives the line the message box would show.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Turn(u32),
+    UsedMove { side: Side, user: String, name: String },
+    NoPp { side: Side, user: String },
+    Missed { side: Side, user: String },
+    Critical,
+    SuperEffective,
+    NotVeryEffective,
+    NoEffect { target: String },
+    // status moves, until they do something
+    NothingHappened,
+    Damage { side: Side, amount: u32, hp: u32 },
+    Fainted { side: Side, name: String },
+    Won(Side),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Turn(n) => write!(f, "Turn {}", n),
+            Event::UsedMove { user, name, .. } => write!(f, "{} used {}!", user, name),
+            Event::NoPp { user, .. } => write!(f, "{} has no PP left for that move!", user),
+            Event::Missed { user, .. } => write!(f, "{}'s attack missed!", user),
+            Event::Critical => write!(f, "A critical hit!"),
+            Event::SuperEffective => write!(f, "It's super effective!"),
+            Event::NotVeryEffective => write!(f, "It's not very effective..."),
+            Event::NoEffect { target } => write!(f, "It doesn't affect {}...", target),
+            Event::NothingHappened => write!(f, "But nothing happened!"),
+            Event::Damage { amount, hp, .. } => write!(f, "({} damage, {} HP left)", amount, hp),
+            Event::Fainted { name, .. } => write!(f, "{} fainted!", name),
+            Event::Won(Side::Player) => write!(f, "You won!"),
+            Event::Won(Side::Opponent) => write!(f, "You lost..."),
+        }
+    }
+}
+
+// A one on one battle, with no rendering or input, one turn at a time. Every random
+// thing comes from the seed, so the same seed and actions give the same log, on any
+// machine and after any update (which is why it's Pcg32 and not StdRng).
+pub struct Battle {
+    player: Creature,
+    opponent: Creature,
+    rng: Pcg32,
+    turn: u32,
+    log: Vec<Event>,
+}
+
+impl Battle {
+    pub fn new(player: Creature, opponent: Creature, seed: u64) -> Self {
+        Battle {
+            player,
+            opponent,
+            rng: Pcg32::seed_from_u64(seed),
+            turn: 0,
+            log: vec![],
+        }
+    }
+
+    pub fn creature(&self, side: Side) -> &Creature {
+        match side {
+            Side::Player => &self.player,
+            Side::Opponent => &self.opponent,
+        }
+    }
+
+    fn creature_mut(&mut self, side: Side) -> &mut Creature {
+        match side {
+            Side::Player => &mut self.player,
+            Side::Opponent => &mut self.opponent,
+        }
+    }
+
+    // everything so far
+    pub fn log(&self) -> &[Event] {
+        &self.log
+    }
+
+    pub fn winner(&self) -> Option<Side> {
+        match (self.player.is_fainted(), self.opponent.is_fainted()) {
+            (_, true) => Some(Side::Player),
+            (true, false) => Some(Side::Opponent),
+            _ => None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner().is_some()
+    }
+
+    fn priority(&self, side: Side, action: Action) -> i8 {
+        match action {
+            Action::Move(i) => self.creature(side).moves.get(i).map_or(0, |m| m.data.priority),
+        }
+    }
+
+    // Who goes first: higher priority, then higher speed, then a coin flip.
+    pub fn order(&mut self, player: Action, opponent: Action) -> [Side; 2] {
+        let priority = (self.priority(Side::Player, player), self.priority(Side::Opponent, opponent));
+        let speed = (self.player.stats.speed, self.opponent.stats.speed);
+
+        let player_first = if priority.0 != priority.1 {
+            priority.0 > priority.1
+        } else if speed.0 != speed.1 {
+            speed.0 > speed.1
+        } else {
+            self.rng.gen()
+        };
+
+        if player_first { [Side::Player, Side::Opponent] } else { [Side::Opponent, Side::Player] }
+    }
+
+    // Plays out a turn and returns what happened in it. Does nothing once it's over.
+    pub fn turn(&mut self, player: Action, opponent: Action) -> &[Event] {
+        let start = self.log.len();
+        if self.is_over() {
+            return &self.log[start..];
+        }
+
+        self.turn += 1;
+        self.log.push(Event::Turn(self.turn));
+
+        for &side in self.order(player, opponent).iter() {
+            let action = if side == Side::Player { player } else { opponent };
+            self.act(side, action);
+
+            if let Some(winner) = self.winner() {
+                self.log.push(Event::Won(winner));
+                break;
+            }
+        }
+
+        &self.log[start..]
+    }
+
+    fn act(&mut self, side: Side, action: Action) {
+        let Action::Move(i) = action;
+        let user = self.creature(side).name.clone();
+
+        let m = match self.creature_mut(side).moves.get_mut(i) {
+            Some(slot) if slot.pp > 0 => {
+                slot.pp -= 1;
+                slot.data.clone()
+            }
+            _ => {
+                self.log.push(Event::NoPp { side, user });
+                return;
+            }
+        };
+        self.log.push(Event::UsedMove { side, user: user.clone(), name: m.name.clone() });
+
+        if let Some(accuracy) = m.accuracy {
+            if self.rng.gen_range(0, 100) >= accuracy {
+                self.log.push(Event::Missed { side, user });
+                return;
+            }
+        }
+
+        if m.category == Category::Status {
+            self.log.push(Event::NothingHappened);
+            return;
+        }
+
+        let crit = self.rng.gen_range(0, CRIT_CHANCE) == 0;
+        let roll = self.rng.gen_range(85, 101);
+        let target = side.other();
+        let (amount, effectiveness) = damage(self.creature(side), self.creature(target), &m, crit, roll);
+
+        if effectiveness == 0.0 {
+            let name = self.creature(target).name.clone();
+            self.log.push(Event::NoEffect { target: name });
+            return;
+        }
+
+        let defender = self.creature_mut(target);
+        defender.hp = defender.hp.saturating_sub(amount);
+        let (hp, fainted, name) = (defender.hp, defender.is_fainted(), defender.name.clone());
+
+        if crit {
+            self.log.push(Event::Critical);
+        }
+        if effectiveness > 1.0 {
+            self.log.push(Event::SuperEffective);
+        } else if effectiveness < 1.0 {
+            self.log.push(Event::NotVeryEffective);
+        }
+        self.log.push(Event::Damage { side: target, amount, hp });
+        if fainted {
+            self.log.push(Event::Fainted { side: target, name });
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn species(name: &str, types: &[Type], base: [u32; 6]) -> Species {
+        Species {
+            name: name.to_string(),
+            types: types.to_vec(),
+            base: Stats { hp: base[0], attack: base[1], defense: base[2], sp_attack: base[3], sp_defense: base[4], speed: base[5] },
+        }
+    }
+
+    fn pikachu() -> Species {
+        species("PIKACHU", &[Type::Electric], [35, 55, 40, 50, 50, 90])
+    }
+
+    fn geodude() -> Species {
+        species("GEODUDE", &[Type::Rock, Type::Ground], [40, 80, 100, 30, 30, 20])
+    }
+
+    fn pidgey() -> Species {
+        species("PIDGEY", &[Type::Normal, Type::Flying], [40, 45, 40, 35, 35, 56])
+    }
+
+    fn moves() -> Vec<Move> {
+        serde_json::from_str(r#"[
+            { "name": "THUNDERBOLT", "type": "electric", "category": "special", "power": 90, "accuracy": 100, "pp": 15 },
+            { "name": "QUICK ATTACK", "type": "normal", "category": "physical", "power": 40, "accuracy": 100, "pp": 30, "priority": 1 },
+            { "name": "TACKLE", "type": "normal", "category": "physical", "power": 40, "accuracy": 100, "pp": 35 },
+            { "name": "GROWL", "type": "normal", "category": "status", "pp": 40 }
+        ]"#).unwrap()
+    }
+
+    fn by_name(name: &str) -> Move {
+        moves().into_iter().find(|m| m.name == name).unwrap()
+    }
+
+    #[test]
+    fn type_chart() {
+        assert_eq!(Type::Water.against(Type::Fire), 2.0);
+        assert_eq!(Type::Fire.against(Type::Water), 0.5);
+        assert_eq!(Type::Normal.against(Type::Ghost), 0.0);
+        assert_eq!(Type::Dragon.against(Type::Fairy), 0.0);
+        assert_eq!(Type::Psychic.against(Type::Normal), 1.0);
+
+        assert_eq!(Type::Water.against_all(&[Type::Rock, Type::Ground]), 4.0);
+        assert_eq!(Type::Electric.against_all(&[Type::Water, Type::Ground]), 0.0);
+        assert_eq!(Type::Fighting.against_all(&[Type::Normal, Type::Flying]), 1.0);
+    }
+
+    #[test]
+    fn stats() {
+        let p = Creature::new(&pikachu(), 50, &moves());
+        assert_eq!(p.stats, Stats { hp: 95, attack: 60, defense: 45, sp_attack: 55, sp_defense: 55, speed: 95 });
+        assert_eq!(p.hp, 95);
+        assert_eq!(p.moves.len(), 4);
+
+        let perfect = Stats { hp: 31, attack: 31, defense: 31, sp_attack: 31, sp_defense: 31, speed: 31 };
+        let p = Creature::with_ivs(&pikachu(), 100, perfect, &[]);
+        assert_eq!((p.stats.hp, p.stats.speed), (211, 216));
+    }
+
+    #[test]
+    fn damage_formula() {
+        let p = Creature::new(&pikachu(), 50, &moves());
+        let g = Creature::new(&pidgey(), 50, &[]);
+
+        // 22 * 90 * 55 / 40 / 50 + 2 = 56, then STAB and flying's weakness
+        assert_eq!(damage(&p, &g, &by_name("THUNDERBOLT"), false, 100), (168, 2.0));
+        assert_eq!(damage(&p, &g, &by_name("THUNDERBOLT"), false, 85), (140, 2.0));
+        assert_eq!(damage(&p, &g, &by_name("THUNDERBOLT"), true, 100), (252, 2.0));
+
+        // no STAB, resisted by rock
+        let rock = Creature::new(&geodude(), 50, &[]);
+        assert_eq!(damage(&p, &rock, &by_name("TACKLE"), false, 100).1, 0.5);
+        assert_eq!(damage(&p, &rock, &by_name("THUNDERBOLT"), false, 100), (0, 0.0));
+        assert_eq!(damage(&p, &g, &by_name("GROWL"), false, 100).0, 0);
+
+        // always at least 1
+        let weak = Creature::new(&pidgey(), 1, &[]);
+        assert_eq!(damage(&weak, &rock, &by_name("TACKLE"), false, 85).0, 1);
+    }
+
+    #[test]
+    fn priority_then_speed() {
+        let mut b = Battle::new(Creature::new(&geodude(), 50, &moves()), Creature::new(&pikachu(), 50, &moves()), 0);
+        // pikachu's faster
+        assert_eq!(b.order(Action::Move(2), Action::Move(2)), [Side::Opponent, Side::Player]);
+        // but quick attack goes first anyway
+        assert_eq!(b.order(Action::Move(1), Action::Move(2)), [Side::Player, Side::Opponent]);
+        assert_eq!(b.order(Action::Move(1), Action::Move(1)), [Side::Opponent, Side::Player]);
+
+        // speed ties are a coin flip, the same one every time with the same seed
+        let flips = |seed| {
+            let mut b = Battle::new(Creature::new(&pikachu(), 50, &moves()), Creature::new(&pikachu(), 50, &moves()), seed);
+            (0..32).map(|_| b.order(Action::Move(2), Action::Move(2))[0]).collect::<Vec<_>>()
+        };
+        let first = flips(7);
+        assert!(first.contains(&Side::Player) && first.contains(&Side::Opponent));
+        assert_eq!(first, flips(7));
+    }
+
+    #[test]
+    fn a_whole_battle() {
+        let play = |seed| {
+            let mut b = Battle::new(Creature::new(&pikachu(), 30, &moves()), Creature::new(&pidgey(), 30, &moves()), seed);
+            while !b.is_over() {
+                b.turn(Action::Move(0), Action::Move(2));
+            }
+            b.log().to_vec()
+        };
+
+        let log = play(3);
+        assert_eq!(log[0], Event::Turn(1));
+        // pikachu's faster, so it goes first
+        assert_eq!(log[1], Event::UsedMove { side: Side::Player, user: "PIKACHU".to_string(), name: "THUNDERBOLT".to_string() });
+        assert!(log.contains(&Event::SuperEffective));
+        assert_eq!(log.last(), Some(&Event::Won(Side::Player)));
+        assert!(log.contains(&Event::Fainted { side: Side::Opponent, name: "PIDGEY".to_string() }));
+        assert_eq!(log, play(3));
+
+        // hp only goes down, and the loser ends at 0
+        let hp: Vec<u32> = log.iter().filter_map(|e| match e {
+            Event::Damage { side: Side::Opponent, hp, .. } => Some(*hp),
+            _ => None,
+        }).collect();
+        assert!(hp.windows(2).all(|w| w[0] > w[1]));
+        assert_eq!(hp.last(), Some(&0));
+    }
+
+    #[test]
+    fn seeds_replay_exactly() {
+        let mut b = Battle::new(Creature::new(&pikachu(), 30, &moves()), Creature::new(&pidgey(), 30, &moves()), 3);
+        while !b.is_over() {
+            b.turn(Action::Move(2), Action::Move(2));
+        }
+        let lines: Vec<String> = b.log().iter().map(|e| e.to_string()).collect();
+
+        // if this changes, so does every recorded battle
+        assert_eq!(lines, vec![
+            "Turn 1",
+            "PIKACHU used TACKLE!", "(13 damage, 51 HP left)",
+            "PIDGEY used TACKLE!", "(19 damage, 42 HP left)",
+            "Turn 2",
+            "PIKACHU used TACKLE!", "(15 damage, 36 HP left)",
+            "PIDGEY used TACKLE!", "(21 damage, 21 HP left)",
+            "Turn 3",
+            "PIKACHU used TACKLE!", "(15 damage, 21 HP left)",
+            "PIDGEY used TACKLE!", "(19 damage, 2 HP left)",
+            "Turn 4",
+            "PIKACHU used TACKLE!", "(15 damage, 6 HP left)",
+            "PIDGEY used TACKLE!", "(19 damage, 0 HP left)",
+            "PIKACHU fainted!",
+            "You lost...",
+        ]);
+    }
+
+    #[test]
+    fn fainted_creatures_dont_move() {
+        let mut b = Battle::new(Creature::new(&pikachu(), 50, &moves()), Creature::new(&pidgey(), 5, &moves()), 1);
+        let events = b.turn(Action::Move(0), Action::Move(2)).to_vec();
+        assert!(events.iter().all(|e| match e {
+            Event::UsedMove { side, .. } => *side == Side::Player,
+            _ => true,
+        }));
+        assert!(b.is_over());
+
+        // nothing happens after the end
+        assert!(b.turn(Action::Move(0), Action::Move(0)).is_empty());
+        assert_eq!(format!("{}", b.log().last().unwrap()), "You won!");
+    }
+
+    #[test]
+    fn pp_and_misses() {
+        let mut sure = by_name("GROWL");
+        sure.pp = 1;
+        let mut wild = by_name("TACKLE");
+        wild.accuracy = Some(0);
+
+        let mut b = Battle::new(Creature::new(&pikachu(), 50, &[sure, wild]), Creature::new(&pidgey(), 50, &moves()), 0);
+        let first = b.turn(Action::Move(0), Action::Move(3)).to_vec();
+        assert!(first.contains(&Event::NothingHappened));
+        assert_eq!(b.creature(Side::Player).moves[0].pp, 0);
+
+        let second = b.turn(Action::Move(0), Action::Move(3)).to_vec();
+        assert!(second.contains(&Event::NoPp { side: Side::Player, user: "PIKACHU".to_string() }));
+
+        let third = b.turn(Action::Move(1), Action::Move(3)).to_vec();
+        assert!(third.contains(&Event::Missed { side: Side::Player, user: "PIKACHU".to_string() }));
+        assert_eq!(format!("{}", third[2]), "PIKACHU's attack missed!");
+    }
+}
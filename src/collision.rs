@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::area::{Area, Direction};
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TileBehavior {
+    Walkable,
+    Solid,
+    // one-way, can only be hopped over moving in this direction
+    Ledge(Direction),
+    // needs surfing
+    Water,
+    // walkable, with wild encounters
+    TallGrass,
+    // solid, but you can talk to whoever is standing behind it
+    Counter,
+}
+
+impl TileBehavior {
+    // somewhere a ledge hop can land
+    fn is_landing(&self) -> bool {
+        match self {
+            TileBehavior::Walkable | TileBehavior::TallGrass => true,
+            _ => false,
+        }
+    }
+}
+
+// What each tile value means, shared by every area drawn with the same tileset.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TileRules {
+    // for tiles that aren't listed
+    #[serde(default = "walkable")]
+    pub default: TileBehavior,
+    // tile 0 is solid unless it's listed here
+    #[serde(default)]
+    pub tiles: HashMap<u32, TileBehavior>,
+}
+
+fn walkable() -> TileBehavior { TileBehavior::Walkable }
+
+// the padding around areas (and between joined ones)
+pub const PADDING: u32 = 0;
+
+impl Default for TileRules {
+    fn default() -> Self {
+        TileRules {
+            default: TileBehavior::Walkable,
+            tiles: HashMap::new(),
+        }
+    }
+}
+
+impl TileRules {
+    pub fn behavior(&self, tile: u32) -> TileBehavior {
+        match self.tiles.get(&tile) {
+            Some(&behavior) => behavior,
+            // so nobody walks off into the gap between joined areas
+            None if tile == PADDING => TileBehavior::Solid,
+            None => self.default,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MoveResult {
+    Walk { to: (i32, i32) },
+    // hopped down a ledge, landing on the tile past it
+    Jump { to: (i32, i32) },
+    // onto water, only allowed if the mover can surf
+    Surf { to: (i32, i32) },
+    Blocked,
+}
+
+impl Area {
+    // tiles outside the area are solid
+    pub fn behavior_at(&self, x: i32, y: i32, rules: &TileRules) -> TileBehavior {
+        if self.contains(x, y) {
+            rules.behavior(self.map[y as usize * self.width + x as usize])
+        } else {
+            TileBehavior::Solid
+        }
+    }
+
+    // Moving one tile in `dir`. This only knows about this area, so crossing into a
+    // neighbor should be checked against the joined map.
+    pub fn can_move(&self, from: (i32, i32), dir: Direction, rules: &TileRules) -> MoveResult {
+        let d = dir.delta();
+        let to = (from.0 + d.0, from.1 + d.1);
+
+        match self.behavior_at(to.0, to.1, rules) {
+            TileBehavior::Walkable | TileBehavior::TallGrass => MoveResult::Walk { to },
+            TileBehavior::Water => MoveResult::Surf { to },
+            TileBehavior::Ledge(ledge) if ledge == dir => {
+                let landing = (to.0 + d.0, to.1 + d.1);
+                if self.behavior_at(landing.0, landing.1, rules).is_landing() {
+                    MoveResult::Jump { to: landing }
+                } else {
+                    MoveResult::Blocked
+                }
+            }
+            TileBehavior::Ledge(_) | TileBehavior::Solid | TileBehavior::Counter => MoveResult::Blocked,
+        }
+    }
+
+    // The tile someone facing `dir` would talk to, reaching across counters.
+    pub fn interaction_target(&self, from: (i32, i32), dir: Direction, rules: &TileRules) -> (i32, i32) {
+        let d = dir.delta();
+        let front = (from.0 + d.0, from.1 + d.1);
+
+        match self.behavior_at(front.0, front.1, rules) {
+            TileBehavior::Counter => (front.0 + d.0, front.1 + d.1),
+            _ => front,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use crate::world::World;
+
+    const GRASS: u32 = 1;
+    const ROCK: u32 = 2;
+    const LEDGE: u32 = 3;
+    const WATER: u32 = 4;
+    const TALL: u32 = 5;
+    const COUNTER: u32 = 6;
+
+    fn rules() -> TileRules {
+        serde_json::from_str(r#"{
+            "tiles": {
+                "0": "solid",
+                "2": "solid",
+                "3": { "ledge": "south" },
+                "4": "water",
+                "5": "tall_grass",
+                "6": "counter"
+            }
+        }"#).unwrap()
+    }
+
+    // 1 1 1 5
+    // 3 3 2 1
+    // 1 4 6 1
+    // 1 4 3 1
+    fn area() -> Area {
+        Area::from(vec![
+            vec![GRASS, GRASS, GRASS, TALL],
+            vec![LEDGE, LEDGE, ROCK, GRASS],
+            vec![GRASS, WATER, COUNTER, GRASS],
+            vec![GRASS, WATER, LEDGE, GRASS],
+        ])
+    }
+
+    #[test]
+    fn rules_lookup() {
+        let r = rules();
+        assert_eq!(r.behavior(GRASS), TileBehavior::Walkable);
+        assert_eq!(r.behavior(LEDGE), TileBehavior::Ledge(Direction::South));
+        assert_eq!(r.behavior(1000), TileBehavior::Walkable);
+        assert_eq!(TileRules::default().behavior(0), TileBehavior::Solid);
+    }
+
+    #[test]
+    fn padding_is_solid() {
+        let empty: TileRules = serde_json::from_str("{}").unwrap();
+        assert_eq!(empty.behavior(0), TileBehavior::Solid);
+        let some: TileRules = serde_json::from_str(r#"{ "tiles": { "4": "water" } }"#).unwrap();
+        assert_eq!(some.behavior(0), TileBehavior::Solid);
+
+        // unless a tileset really wants tile 0 for something
+        let over: TileRules = serde_json::from_str(r#"{ "tiles": { "0": "water" } }"#).unwrap();
+        assert_eq!(over.behavior(0), TileBehavior::Water);
+    }
+
+    #[test]
+    fn walk_and_grass() {
+        let a = area();
+        let r = rules();
+        assert_eq!(a.can_move((0, 0), Direction::East, &r), MoveResult::Walk { to: (1, 0) });
+        assert_eq!(a.can_move((2, 0), Direction::East, &r), MoveResult::Walk { to: (3, 0) });
+        assert_eq!(a.behavior_at(3, 0, &r), TileBehavior::TallGrass);
+    }
+
+    #[test]
+    fn solid_and_edges() {
+        let a = area();
+        let r = rules();
+        assert_eq!(a.can_move((2, 0), Direction::South, &r), MoveResult::Blocked);
+        assert_eq!(a.can_move((0, 0), Direction::North, &r), MoveResult::Blocked);
+        assert_eq!(a.can_move((0, 0), Direction::West, &r), MoveResult::Blocked);
+        assert_eq!(a.can_move((3, 3), Direction::South, &r), MoveResult::Blocked);
+        assert_eq!(a.can_move((3, 3), Direction::East, &r), MoveResult::Blocked);
+    }
+
+    #[test]
+    fn ledges_are_one_way() {
+        let a = area();
+        let r = rules();
+        // down the ledge, landing past it
+        assert_eq!(a.can_move((0, 0), Direction::South, &r), MoveResult::Jump { to: (0, 2) });
+        // not back up
+        assert_eq!(a.can_move((0, 2), Direction::North, &r), MoveResult::Blocked);
+        // and not onto it from the side
+        let mut side = area();
+        side.set_tile(2, 1, GRASS);
+        assert_eq!(side.can_move((2, 1), Direction::West, &r), MoveResult::Blocked);
+    }
+
+    #[test]
+    fn ledges_need_somewhere_to_land() {
+        let a = area();
+        let r = rules();
+        // water below
+        assert_eq!(a.can_move((1, 0), Direction::South, &r), MoveResult::Blocked);
+        // off the edge of the area
+        assert_eq!(a.can_move((2, 2), Direction::South, &r), MoveResult::Blocked);
+    }
+
+    #[test]
+    fn water() {
+        let a = area();
+        let r = rules();
+        assert_eq!(a.can_move((0, 2), Direction::East, &r), MoveResult::Surf { to: (1, 2) });
+        assert_eq!(a.can_move((1, 2), Direction::South, &r), MoveResult::Surf { to: (1, 3) });
+        // and back onto land
+        assert_eq!(a.can_move((1, 3), Direction::West, &r), MoveResult::Walk { to: (0, 3) });
+    }
+
+    #[test]
+    fn counters() {
+        let a = area();
+        let r = rules();
+        assert_eq!(a.can_move((3, 2), Direction::West, &r), MoveResult::Blocked);
+        assert_eq!(a.interaction_target((3, 2), Direction::West, &r), (1, 2));
+        assert_eq!(a.interaction_target((3, 2), Direction::North, &r), (3, 1));
+    }
+
+    // a town with a route east of it, the town's bottom right tile is a ledge facing east
+    fn world() -> World {
+        let mut areas = std::collections::HashMap::new();
+        for json in [
+            r#"{ "east": { "offset": 0, "area": "route" },
+                 "tilemap": { "width": 2, "height": 2, "map": [1, 1, 1, 7] }, "name": "town" }"#,
+            r#"{ "west": { "offset": 0, "area": "town" },
+                 "tilemap": { "width": 3, "height": 2, "map": [1, 3, 2, 1, 1, 1] }, "name": "route" }"#,
+        ].iter() {
+            let area: Area = serde_json::from_str(json).unwrap();
+            areas.insert(area.name.clone(), Rc::new(area));
+        }
+        World::new(areas, "town").unwrap()
+    }
+
+    #[test]
+    fn seams() {
+        let w = world();
+        let mut r = rules();
+        r.tiles.insert(7, TileBehavior::Ledge(Direction::East));
+        let joined = w.joined();
+        let at = |pos: (i32, i32)| w.to_joined(pos);
+
+        // on its own the town doesn't know about the route
+        assert_eq!(w.current().can_move((1, 0), Direction::East, &r), MoveResult::Blocked);
+
+        // walking and hopping through the seam
+        assert_eq!(joined.can_move(at((1, 0)), Direction::East, &r), MoveResult::Walk { to: at((2, 0)) });
+        assert_eq!(joined.can_move(at((0, 1)), Direction::East, &r), MoveResult::Jump { to: at((2, 1)) });
+        // but not back up the ledge
+        assert_eq!(joined.can_move(at((2, 1)), Direction::West, &r), MoveResult::Blocked);
+
+        // the route's own ledge and rock
+        assert_eq!(joined.can_move(at((3, 1)), Direction::North, &r), MoveResult::Blocked);
+        assert_eq!(joined.can_move(at((4, 1)), Direction::North, &r), MoveResult::Blocked);
+        assert_eq!(joined.can_move(at((2, 0)), Direction::North, &r), MoveResult::Blocked);
+    }
+}
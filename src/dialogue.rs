@@ -0,0 +1,333 @@
+use crate::font::BitmapFont;
+use crate::input::{Button, Input};
+
+// typing goes this many times faster while A or B is held
+const HELD_SPEEDUP: u32 = 4;
+// ticks the "more text" arrow spends on, then off
+const ARROW_BLINK: u32 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DialogueConfig {
+    // of the text inside the box, in pixels
+    pub width: u32,
+    pub lines: usize,
+    // 0 shows each page all at once
+    pub ticks_per_char: u32,
+}
+
+impl Default for DialogueConfig {
+    fn default() -> Self {
+        DialogueConfig {
+            width: 224,
+            lines: 2,
+            ticks_per_char: 2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DialogueState {
+    Typing,
+    // page is out, waiting for a press with the arrow blinking
+    Waiting,
+    Choosing,
+    Done,
+}
+
+// The message box: text typed out a page at a time, optionally ending in a choice.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dialogue {
+    text: String,
+    pages: Vec<Vec<String>>,
+    page: usize,
+    // chars of the page shown so far, not counting line breaks
+    revealed: usize,
+    // ticks towards the next char
+    timer: u32,
+    ticks_per_char: u32,
+    blink: u32,
+    options: Vec<String>,
+    cursor: usize,
+    choice: Option<usize>,
+    state: DialogueState,
+}
+
+impl Dialogue {
+    pub fn new(text: &str, font: &BitmapFont, config: &DialogueConfig) -> Self {
+        let lines = font.wrap(text, config.width);
+        let pages = lines.chunks(config.lines.max(1)).map(|p| p.to_vec()).collect();
+
+        let mut dialogue = Dialogue {
+            text: text.to_string(),
+            pages,
+            page: 0,
+            revealed: 0,
+            timer: 0,
+            ticks_per_char: config.ticks_per_char,
+            blink: 0,
+            options: vec![],
+            cursor: 0,
+            choice: None,
+            state: DialogueState::Typing,
+        };
+        if config.ticks_per_char == 0 {
+            dialogue.reveal_page();
+        }
+        dialogue
+    }
+
+    // Asks a question once the text is out. B picks the last option, so put
+    // "No" or "Cancel" there.
+    pub fn ask(text: &str, options: &[String], font: &BitmapFont, config: &DialogueConfig) -> Self {
+        let mut dialogue = Dialogue::new(text, font, config);
+        dialogue.options = options.to_vec();
+        if dialogue.state == DialogueState::Waiting && dialogue.is_last_page() {
+            dialogue.state = DialogueState::Choosing;
+        }
+        dialogue
+    }
+
+    pub fn yes_no(text: &str, font: &BitmapFont, config: &DialogueConfig) -> Self {
+        Dialogue::ask(text, &["YES".to_string(), "NO".to_string()], font, config)
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn state(&self) -> DialogueState {
+        self.state
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == DialogueState::Done
+    }
+
+    // the lines in the box right now, all of them, even the parts not revealed yet
+    pub fn page(&self) -> &[String] {
+        &self.pages[self.page]
+    }
+
+    pub fn revealed(&self) -> usize {
+        self.revealed
+    }
+
+    pub fn arrow_visible(&self) -> bool {
+        self.state == DialogueState::Waiting && (self.blink / ARROW_BLINK) % 2 == 0
+    }
+
+    pub fn options(&self) -> &[String] {
+        &self.options
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    // what was picked, once done
+    pub fn choice(&self) -> Option<usize> {
+        self.choice
+    }
+
+    fn page_len(&self) -> usize {
+        self.page().iter().map(|l| l.chars().count()).sum()
+    }
+
+    fn is_last_page(&self) -> bool {
+        self.page + 1 >= self.pages.len()
+    }
+
+    fn reveal_page(&mut self) {
+        self.revealed = self.page_len();
+        self.blink = 0;
+        self.state = if self.is_last_page() && !self.options.is_empty() {
+            DialogueState::Choosing
+        } else {
+            DialogueState::Waiting
+        };
+    }
+
+    pub fn update(&mut self, input: &Input) -> DialogueState {
+        let confirm = input.pressed(Button::A) || input.pressed(Button::B);
+
+        match self.state {
+            DialogueState::Typing => {
+                let held = input.held(Button::A) || input.held(Button::B);
+                self.timer += if held { HELD_SPEEDUP } else { 1 };
+
+                while self.timer >= self.ticks_per_char && self.revealed < self.page_len() {
+                    self.timer -= self.ticks_per_char;
+                    self.revealed += 1;
+                }
+                if self.revealed >= self.page_len() {
+                    self.timer = 0;
+                    self.reveal_page();
+                }
+            }
+            DialogueState::Waiting => {
+                self.blink += 1;
+                if confirm {
+                    if self.is_last_page() {
+                        self.state = DialogueState::Done;
+                    } else {
+                        self.page += 1;
+                        self.revealed = 0;
+                        self.state = DialogueState::Typing;
+                        if self.ticks_per_char == 0 {
+                            self.reveal_page();
+                        }
+                    }
+                }
+            }
+            DialogueState::Choosing => {
+                let n = self.options.len();
+                if input.pressed(Button::Up) {
+                    self.cursor = (self.cursor + n - 1) % n;
+                }
+                if input.pressed(Button::Down) {
+                    self.cursor = (self.cursor + 1) % n;
+                }
+                if input.pressed(Button::A) {
+                    self.choice = Some(self.cursor);
+                    self.state = DialogueState::Done;
+                } else if input.pressed(Button::B) {
+                    self.choice = Some(n - 1);
+                    self.state = DialogueState::Done;
+                }
+            }
+            DialogueState::Done => {}
+        }
+
+        self.state
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::Spacing;
+
+    fn config(ticks_per_char: u32) -> DialogueConfig {
+        // 6px chars, so 10 to a line
+        DialogueConfig { width: 59, lines: 2, ticks_per_char }
+    }
+
+    fn font() -> BitmapFont {
+        BitmapFont::builtin(Spacing::Fixed)
+    }
+
+    fn press(button: Button) -> Input {
+        let mut input = Input::default();
+        input.press(button);
+        input
+    }
+
+    // ticks until the state changes
+    fn ticks_until_not(d: &mut Dialogue, state: DialogueState, input: &Input) -> u32 {
+        let mut ticks = 0;
+        while d.state() == state {
+            d.update(input);
+            ticks += 1;
+            assert!(ticks < 10000);
+        }
+        ticks
+    }
+
+    #[test]
+    fn types_out_pages() {
+        let mut d = Dialogue::new("Hello there! Welcome to the world of POKéMON!", &font(), &config(2));
+        assert_eq!(d.page(), ["Hello", "there!"]);
+
+        let none = Input::default();
+        d.update(&none);
+        assert_eq!(d.revealed(), 0);
+        d.update(&none);
+        assert_eq!(d.revealed(), 1);
+
+        // 11 chars at 2 ticks each
+        assert_eq!(ticks_until_not(&mut d, DialogueState::Typing, &none) + 2, 22);
+        assert_eq!(d.state(), DialogueState::Waiting);
+        assert!(d.arrow_visible());
+
+        // stays until a press
+        for _ in 0..100 {
+            d.update(&none);
+        }
+        assert_eq!(d.state(), DialogueState::Waiting);
+
+        d.update(&press(Button::A));
+        assert_eq!((d.state(), d.revealed()), (DialogueState::Typing, 0));
+        assert_eq!(d.page(), ["Welcome to", "the world"]);
+    }
+
+    #[test]
+    fn holding_speeds_it_up() {
+        let text = "abcdefgh";
+        let slow = ticks_until_not(&mut Dialogue::new(text, &font(), &config(4)), DialogueState::Typing, &Input::default());
+
+        let mut held = press(Button::B);
+        held.end_tick();
+        let fast = ticks_until_not(&mut Dialogue::new(text, &font(), &config(4)), DialogueState::Typing, &held);
+
+        assert_eq!((slow, fast), (32, 8));
+    }
+
+    #[test]
+    fn instant_text() {
+        let mut d = Dialogue::new("one two three four five", &font(), &config(0));
+        assert_eq!(d.state(), DialogueState::Waiting);
+        assert_eq!(d.page(), ["one two", "three four"]);
+        assert_eq!(d.revealed(), 17);
+
+        d.update(&press(Button::A));
+        assert_eq!((d.state(), d.revealed()), (DialogueState::Waiting, 4));
+        assert_eq!(d.state(), DialogueState::Waiting);
+        d.update(&press(Button::A));
+        assert!(d.is_done());
+        assert_eq!(d.choice(), None);
+    }
+
+    #[test]
+    fn arrow_blinks() {
+        let mut d = Dialogue::new("hi", &font(), &config(0));
+        let none = Input::default();
+        let mut seen = vec![];
+        for _ in 0..4 * ARROW_BLINK {
+            seen.push(d.arrow_visible());
+            d.update(&none);
+        }
+        assert_eq!(seen.iter().filter(|&&v| v).count(), 2 * ARROW_BLINK as usize);
+        assert!(seen[0] && !seen[ARROW_BLINK as usize]);
+    }
+
+    #[test]
+    fn choices_wrap_around() {
+        let options: Vec<String> = ["BULBASAUR", "CHARMANDER", "SQUIRTLE"].iter().map(|s| s.to_string()).collect();
+        let mut d = Dialogue::ask("Which one?", &options, &font(), &config(0));
+        // no waiting for a press on the question itself
+        assert_eq!(d.state(), DialogueState::Choosing);
+        assert!(!d.arrow_visible());
+
+        d.update(&press(Button::Up));
+        assert_eq!(d.cursor(), 2);
+        d.update(&press(Button::Down));
+        d.update(&press(Button::Down));
+        assert_eq!(d.cursor(), 1);
+
+        d.update(&press(Button::A));
+        assert!(d.is_done());
+        assert_eq!(d.choice(), Some(1));
+    }
+
+    #[test]
+    fn b_says_no() {
+        let mut d = Dialogue::yes_no("Save the game?", &font(), &config(1));
+        assert_eq!(d.state(), DialogueState::Typing);
+        ticks_until_not(&mut d, DialogueState::Typing, &Input::default());
+        assert_eq!(d.state(), DialogueState::Choosing);
+
+        d.update(&press(Button::B));
+        assert_eq!(d.choice(), Some(1));
+    }
+}
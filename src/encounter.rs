@@ -0,0 +1,182 @@
+use serde::{Serialize, Deserialize};
+use rand::Rng;
+use crate::area::Area;
+use crate::collision::TileBehavior;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Terrain {
+    Grass,
+    Water,
+    // any floor tile, in areas that have a cave table
+    Cave,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncounterSlot {
+    pub species: String,
+    pub min_level: u8,
+    pub max_level: u8,
+    #[serde(default = "one")]
+    pub weight: u32,
+}
+
+fn one() -> u32 { 1 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EncounterTable {
+    // chance of an encounter per step, out of 256
+    pub rate: u32,
+    pub slots: Vec<EncounterSlot>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WildEncounter {
+    pub species: String,
+    pub level: u8,
+}
+
+impl EncounterTable {
+    // Called once per step onto this table's terrain.
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> Option<WildEncounter> {
+        if rng.gen_range(0, 256) >= self.rate {
+            return None;
+        }
+
+        let total: u32 = self.slots.iter().map(|s| s.weight).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut pick = rng.gen_range(0, total);
+        let slot = self.slots.iter().find(|s| {
+            if pick < s.weight {
+                true
+            } else {
+                pick -= s.weight;
+                false
+            }
+        })?;
+
+        let (min, max) = (slot.min_level, std::cmp::max(slot.min_level, slot.max_level));
+        Some(WildEncounter {
+            species: slot.species.clone(),
+            level: rng.gen_range(min as u32, max as u32 + 1) as u8,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Encounters {
+    #[serde(default)]
+    pub grass: Option<EncounterTable>,
+    #[serde(default)]
+    pub water: Option<EncounterTable>,
+    #[serde(default)]
+    pub cave: Option<EncounterTable>,
+}
+
+impl Encounters {
+    pub fn table(&self, terrain: Terrain) -> Option<&EncounterTable> {
+        match terrain {
+            Terrain::Grass => self.grass.as_ref(),
+            Terrain::Water => self.water.as_ref(),
+            Terrain::Cave => self.cave.as_ref(),
+        }
+    }
+
+    // which table (if any) a step onto a tile with this behavior rolls against
+    pub fn terrain(&self, behavior: TileBehavior) -> Option<Terrain> {
+        match behavior {
+            TileBehavior::TallGrass => Some(Terrain::Grass),
+            TileBehavior::Water => Some(Terrain::Water),
+            TileBehavior::Walkable if self.cave.is_some() => Some(Terrain::Cave),
+            _ => None,
+        }
+    }
+}
+
+impl Area {
+    pub fn roll_encounter<R: Rng>(&self, behavior: TileBehavior, rng: &mut R) -> Option<WildEncounter> {
+        let terrain = self.encounters.terrain(behavior)?;
+        self.encounters.table(terrain)?.roll(rng)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn table(rate: u32) -> EncounterTable {
+        serde_json::from_str(&format!(r#"{{
+            "rate": {},
+            "slots": [
+                {{ "species": "rattata", "min_level": 2, "max_level": 4, "weight": 3 }},
+                {{ "species": "pidgey", "min_level": 3, "max_level": 3 }}
+            ]
+        }}"#, rate)).unwrap()
+    }
+
+    fn rolls(t: &EncounterTable, seed: u64, n: usize) -> Vec<Option<WildEncounter>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| t.roll(&mut rng)).collect()
+    }
+
+    #[test]
+    fn same_seed_same_encounters() {
+        let t = table(40);
+        assert_eq!(rolls(&t, 7, 200), rolls(&t, 7, 200));
+        assert_ne!(rolls(&t, 7, 200), rolls(&t, 8, 200));
+    }
+
+    #[test]
+    fn rate_bounds() {
+        assert!(rolls(&table(0), 1, 500).iter().all(|e| e.is_none()));
+        assert!(rolls(&table(256), 1, 500).iter().all(|e| e.is_some()));
+    }
+
+    #[test]
+    fn weights_and_levels() {
+        let found: Vec<WildEncounter> = rolls(&table(256), 3, 4000).into_iter().map(|e| e.unwrap()).collect();
+
+        let rattata = found.iter().filter(|e| e.species == "rattata").count();
+        // 3 in 4
+        assert!(rattata > 2800 && rattata < 3200, "{}", rattata);
+
+        for e in found.iter() {
+            match e.species.as_str() {
+                "rattata" => assert!(e.level >= 2 && e.level <= 4),
+                "pidgey" => assert_eq!(e.level, 3),
+                _ => panic!("unexpected {:?}", e),
+            }
+        }
+        assert!(found.iter().any(|e| e.level == 4));
+    }
+
+    #[test]
+    fn terrain_for_tiles() {
+        let mut e = Encounters::default();
+        assert_eq!(e.terrain(TileBehavior::TallGrass), Some(Terrain::Grass));
+        assert_eq!(e.terrain(TileBehavior::Water), Some(Terrain::Water));
+        assert_eq!(e.terrain(TileBehavior::Walkable), None);
+
+        e.cave = Some(table(10));
+        assert_eq!(e.terrain(TileBehavior::Walkable), Some(Terrain::Cave));
+        assert_eq!(e.terrain(TileBehavior::Solid), None);
+    }
+
+    #[test]
+    fn area_without_a_table() {
+        let area: Area = serde_json::from_str(r#"{
+            "tilemap": { "width": 1, "height": 1, "map": [1] }, "name": "town",
+            "encounters": { "water": { "rate": 256, "slots": [ { "species": "magikarp", "min_level": 5, "max_level": 5 } ] } }
+        }"#).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(area.roll_encounter(TileBehavior::TallGrass, &mut rng), None);
+        assert_eq!(area.roll_encounter(TileBehavior::Water, &mut rng).unwrap().species, "magikarp");
+    }
+}
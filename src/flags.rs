@@ -0,0 +1,107 @@
+use std::collections::{BTreeMap, BTreeSet};
+use serde::{Serialize, Deserialize};
+
+// Named game state that outlives areas and sessions, e.g. "beat_brock" or how many
+// times you've talked to someone. Sorted so saves come out the same every time.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Flags {
+    #[serde(default)]
+    flags: BTreeSet<String>,
+    #[serde(default)]
+    vars: BTreeMap<String, i32>,
+}
+
+impl Flags {
+    // a name that only means something in one area, e.g. an item ball on a route
+    pub fn local(area: &str, name: &str) -> String {
+        format!("{}/{}", area, name)
+    }
+
+    pub fn get(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) {
+        if value {
+            self.flags.insert(name.to_string());
+        } else {
+            self.flags.remove(name);
+        }
+    }
+
+    // unset variables are 0
+    pub fn var(&self, name: &str) -> i32 {
+        self.vars.get(name).cloned().unwrap_or(0)
+    }
+
+    pub fn set_var(&mut self, name: &str, value: i32) {
+        if value == 0 {
+            self.vars.remove(name);
+        } else {
+            self.vars.insert(name.to_string(), value);
+        }
+    }
+
+    pub fn add_var(&mut self, name: &str, delta: i32) {
+        let value = self.var(name).saturating_add(delta);
+        self.set_var(name, value);
+    }
+
+    // everything set in one area, without the area prefix
+    pub fn area_flags<'a>(&'a self, area: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let prefix = format!("{}/", area);
+        let skip = prefix.len();
+        self.flags.iter()
+            .filter(move |f| f.starts_with(&prefix))
+            .map(move |f| &f[skip..])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_and_vars() {
+        let mut f = Flags::default();
+        assert!(!f.get("beat_brock"));
+        f.set("beat_brock", true);
+        assert!(f.get("beat_brock"));
+        f.set("beat_brock", false);
+        assert!(!f.get("beat_brock"));
+
+        assert_eq!(f.var("talked"), 0);
+        f.add_var("talked", 2);
+        f.add_var("talked", 1);
+        assert_eq!(f.var("talked"), 3);
+        f.set_var("talked", i32::max_value());
+        f.add_var("talked", 1);
+        assert_eq!(f.var("talked"), i32::max_value());
+    }
+
+    #[test]
+    fn namespaced() {
+        let mut f = Flags::default();
+        f.set(&Flags::local("route1", "potion"), true);
+        f.set(&Flags::local("route10", "potion"), true);
+        f.set("potion", true);
+
+        assert!(f.get("route1/potion"));
+        assert_eq!(f.area_flags("route1").collect::<Vec<_>>(), vec!["potion"]);
+    }
+
+    #[test]
+    fn serializes_sorted() {
+        let mut f = Flags::default();
+        f.set("b", true);
+        f.set("a", true);
+        f.set_var("x", 5);
+        f.set_var("zero", 0);
+
+        let json = serde_json::to_string(&f).unwrap();
+        assert_eq!(json, r#"{"flags":["a","b"],"vars":{"x":5}}"#);
+        assert_eq!(serde_json::from_str::<Flags>(&json).unwrap(), f);
+        assert_eq!(serde_json::from_str::<Flags>("{}").unwrap(), Flags::default());
+    }
+}
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+
+// 5x7 glyphs, one row per byte from the top, bit 4 is the leftmost column
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('"', [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('$', [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('&', [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    (';', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('@', [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E]),
+    ('A', [0x0E, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    ('\\', [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('^', [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('`', [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00]),
+    ('a', [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F]),
+    ('b', [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E]),
+    ('c', [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E]),
+    ('d', [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F]),
+    ('e', [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E]),
+    ('f', [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08]),
+    ('g', [0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E]),
+    ('h', [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11]),
+    ('i', [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E]),
+    ('j', [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0C]),
+    ('k', [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12]),
+    ('l', [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('m', [0x00, 0x00, 0x1A, 0x15, 0x15, 0x11, 0x11]),
+    ('n', [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11]),
+    ('o', [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E]),
+    ('p', [0x00, 0x00, 0x1E, 0x11, 0x1E, 0x10, 0x10]),
+    ('q', [0x00, 0x00, 0x0D, 0x13, 0x0F, 0x01, 0x01]),
+    ('r', [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10]),
+    ('s', [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E]),
+    ('t', [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06]),
+    ('u', [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D]),
+    ('v', [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('w', [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A]),
+    ('x', [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11]),
+    ('y', [0x00, 0x00, 0x11, 0x11, 0x0F, 0x01, 0x0E]),
+    ('z', [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F]),
+    ('{', [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02]),
+    ('|', [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('}', [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08]),
+    ('~', [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00]),
+    ('é', [0x02, 0x04, 0x0E, 0x11, 0x1F, 0x10, 0x0E]),
+    ('…', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x15]),
+    // the "more text" arrow and the menu cursor
+    ('▼', [0x00, 0x00, 0x1F, 0x0E, 0x04, 0x00, 0x00]),
+    ('▶', [0x08, 0x0C, 0x0E, 0x0F, 0x0E, 0x0C, 0x08]),
+];
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// atlas cells have a pixel of padding so nearest sampling never bleeds
+const CELL: u32 = 8;
+const ATLAS_COLUMNS: u32 = 16;
+// drawn for anything the font doesn't have
+const FALLBACK: char = '?';
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Spacing {
+    // every glyph takes the same space, like a terminal
+    Fixed,
+    // glyphs are trimmed to their ink, e.g. 'i' is narrower than 'm'
+    Variable,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Glyph {
+    // in the atlas, including the trimmed columns
+    u: u32,
+    v: u32,
+    // first and one-past-last column with ink
+    left: u32,
+    right: u32,
+}
+
+// Where one glyph lands, in pixels relative to the layout's top left.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GlyphQuad {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    // top left of the glyph in the atlas
+    pub u: u32,
+    pub v: u32,
+    // which char of the laid out text this is, e.g. for revealing text a char at a time
+    pub index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub lines: Vec<String>,
+    pub quads: Vec<GlyphQuad>,
+    // of the widest line
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct BitmapFont {
+    glyphs: HashMap<char, Glyph>,
+    pub spacing: Spacing,
+    pub line_height: u32,
+    atlas_size: [u32; 2],
+    // one byte per pixel, 255 where there's ink
+    atlas: Vec<u8>,
+}
+
+impl BitmapFont {
+    pub fn builtin(spacing: Spacing) -> Self {
+        let rows = (GLYPHS.len() as u32 + ATLAS_COLUMNS - 1) / ATLAS_COLUMNS;
+        let atlas_size = [ATLAS_COLUMNS * CELL, rows * CELL];
+        let mut atlas = vec![0u8; (atlas_size[0] * atlas_size[1]) as usize];
+        let mut glyphs = HashMap::new();
+
+        for (i, &(c, bits)) in GLYPHS.iter().enumerate() {
+            let (u, v) = ((i as u32 % ATLAS_COLUMNS) * CELL, (i as u32 / ATLAS_COLUMNS) * CELL);
+
+            let mut ink = 0u8;
+            for (y, row) in bits.iter().enumerate() {
+                ink |= row;
+                for x in 0..GLYPH_WIDTH {
+                    if row & (0x10 >> x) != 0 {
+                        atlas[((v + y as u32) * atlas_size[0] + u + x) as usize] = 255;
+                    }
+                }
+            }
+
+            let (left, right) = if ink == 0 {
+                // space, keep some width
+                (0, 2)
+            } else {
+                (ink.leading_zeros() - 3, GLYPH_WIDTH - ink.trailing_zeros())
+            };
+
+            glyphs.insert(c, Glyph { u, v, left, right });
+        }
+
+        BitmapFont {
+            glyphs,
+            spacing,
+            line_height: GLYPH_HEIGHT + 3,
+            atlas_size,
+            atlas,
+        }
+    }
+
+    pub fn atlas_size(&self) -> [u32; 2] {
+        self.atlas_size
+    }
+
+    pub fn atlas(&self) -> &[u8] {
+        &self.atlas
+    }
+
+    fn glyph(&self, c: char) -> Glyph {
+        self.glyphs.get(&c).or(self.glyphs.get(&FALLBACK)).cloned().unwrap()
+    }
+
+    // the glyph's drawn columns
+    fn columns(&self, c: char) -> (u32, u32) {
+        let g = self.glyph(c);
+        match self.spacing {
+            Spacing::Fixed => (0, GLYPH_WIDTH),
+            Spacing::Variable => (g.left, g.right),
+        }
+    }
+
+    // how far the pen moves after a char, including the gap to the next one
+    pub fn advance(&self, c: char) -> u32 {
+        let (left, right) = self.columns(c);
+        right - left + 1
+    }
+
+    pub fn measure(&self, line: &str) -> u32 {
+        let width: u32 = line.chars().map(|c| self.advance(c)).sum();
+        // no gap after the last char
+        width.saturating_sub(1)
+    }
+
+    // Breaks text into lines no wider than max_width, at spaces where possible. Newlines
+    // always break. Words too long for a line on their own are split wherever they overflow.
+    pub fn wrap(&self, text: &str, max_width: u32) -> Vec<String> {
+        let mut lines = vec![];
+
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+
+            for word in paragraph.split(' ') {
+                let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+                if self.measure(&candidate) <= max_width {
+                    line = candidate;
+                    continue;
+                }
+
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+
+                // the word alone might still be too long
+                line = String::new();
+                for c in word.chars() {
+                    line.push(c);
+                    if self.measure(&line) > max_width && line.chars().count() > 1 {
+                        line.pop();
+                        lines.push(line);
+                        line = c.to_string();
+                    }
+                }
+            }
+
+            lines.push(line);
+        }
+
+        lines
+    }
+
+    // Quads for already wrapped lines. Indices count chars across lines, not counting line breaks.
+    pub fn layout_lines(&self, lines: &[String]) -> TextLayout {
+        let mut quads = vec![];
+        let mut index = 0;
+
+        for (row, line) in lines.iter().enumerate() {
+            let mut x = 0;
+            for c in line.chars() {
+                let g = self.glyph(c);
+                let (left, right) = self.columns(c);
+
+                if c != ' ' {
+                    quads.push(GlyphQuad {
+                        x: x as i32,
+                        y: (row as u32 * self.line_height) as i32,
+                        width: right - left,
+                        height: GLYPH_HEIGHT,
+                        u: g.u + left,
+                        v: g.v,
+                        index,
+                    });
+                }
+
+                x += self.advance(c);
+                index += 1;
+            }
+        }
+
+        TextLayout {
+            lines: lines.to_vec(),
+            quads,
+            width: lines.iter().map(|l| self.measure(l)).max().unwrap_or(0),
+            height: lines.len() as u32 * self.line_height,
+        }
+    }
+
+    pub fn layout(&self, text: &str, max_width: u32) -> TextLayout {
+        self.layout_lines(&self.wrap(text, max_width))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ink(font: &BitmapFont, u: u32, v: u32) -> bool {
+        font.atlas()[(v * font.atlas_size()[0] + u) as usize] != 0
+    }
+
+    #[test]
+    fn atlas_matches_the_bits() {
+        let font = BitmapFont::builtin(Spacing::Fixed);
+        let size = font.atlas_size();
+        assert_eq!(font.atlas().len(), (size[0] * size[1]) as usize);
+
+        let a = font.glyph('A');
+        // top row of A is .###.
+        assert!(!ink(&font, a.u, a.v));
+        assert!(ink(&font, a.u + 1, a.v));
+        assert!(ink(&font, a.u + 3, a.v));
+        assert!(!ink(&font, a.u + 4, a.v));
+        // padding row
+        assert!((0..CELL).all(|x| !ink(&font, a.u + x, a.v + 7)));
+    }
+
+    #[test]
+    fn fixed_and_variable_widths() {
+        let fixed = BitmapFont::builtin(Spacing::Fixed);
+        assert_eq!(fixed.advance('i'), 6);
+        assert_eq!(fixed.advance('m'), 6);
+        assert_eq!(fixed.measure("hi"), 11);
+
+        let variable = BitmapFont::builtin(Spacing::Variable);
+        assert_eq!(variable.advance('i'), 4);
+        assert_eq!(variable.advance('m'), 6);
+        assert_eq!(variable.advance('.'), 3);
+        assert_eq!(variable.advance(' '), 3);
+        assert_eq!(variable.measure(""), 0);
+    }
+
+    #[test]
+    fn unknown_chars_fall_back() {
+        let font = BitmapFont::builtin(Spacing::Variable);
+        assert_eq!(font.glyph('字'), font.glyph('?'));
+        assert_ne!(font.glyph('é'), font.glyph('?'));
+    }
+
+    #[test]
+    fn wraps_at_spaces() {
+        let font = BitmapFont::builtin(Spacing::Fixed);
+        // 6px a char, so 8 chars fit in 47px
+        assert_eq!(font.wrap("the quick brown fox", 47), vec!["the", "quick", "brown", "fox"]);
+        assert_eq!(font.wrap("the quick brown fox", 59), vec!["the quick", "brown fox"]);
+        assert_eq!(font.wrap("one\ntwo three", 200), vec!["one", "two three"]);
+        assert_eq!(font.wrap("", 10), vec![""]);
+    }
+
+    #[test]
+    fn splits_long_words() {
+        let font = BitmapFont::builtin(Spacing::Fixed);
+        assert_eq!(font.wrap("a abcdefghij", 29), vec!["a", "abcde", "fghij"]);
+        // doesn't loop forever when not even one char fits
+        assert_eq!(font.wrap("ab", 1), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn utf8() {
+        let font = BitmapFont::builtin(Spacing::Fixed);
+        let layout = font.layout("Pokémon", 100);
+        assert_eq!(layout.quads.len(), 7);
+        assert_eq!(layout.quads[3].x, 18);
+        assert_eq!(layout.quads[3].u, font.glyph('é').u);
+        assert_eq!(layout.width, 41);
+    }
+
+    #[test]
+    fn quads() {
+        let font = BitmapFont::builtin(Spacing::Variable);
+        let layout = font.layout("Hi there", 30);
+        assert_eq!(layout.lines, vec!["Hi", "there"]);
+        assert_eq!(layout.height, 2 * font.line_height);
+
+        // spaces take room but don't get a quad, the indices still count them
+        let firsts: Vec<(i32, i32, usize)> = layout.quads.iter().map(|q| (q.x, q.y, q.index)).collect();
+        assert_eq!(firsts[..3], [(0, 0, 0), (6, 0, 1), (0, 10, 2)]);
+
+        // i is trimmed to its ink, both in size and where it's read from the atlas
+        let i = layout.quads[1];
+        assert_eq!((i.width, i.u), (3, font.glyph('i').u + 1));
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+
+// What the game reacts to, whatever key or pad button it came from.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Start,
+    Select,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Up, Button::Down, Button::Left, Button::Right,
+        Button::A, Button::B, Button::Start, Button::Select,
+    ];
+}
+
+// The buttons for one tick. Feed it presses and releases as they come in, read it,
+// then call end_tick so presses only count once.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Input {
+    held: HashSet<Button>,
+    pressed: HashSet<Button>,
+}
+
+impl Input {
+    pub fn press(&mut self, button: Button) {
+        if self.held.insert(button) {
+            self.pressed.insert(button);
+        }
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.held.remove(&button);
+    }
+
+    pub fn end_tick(&mut self) {
+        self.pressed.clear();
+    }
+
+    pub fn held(&self, button: Button) -> bool {
+        self.held.contains(&button)
+    }
+
+    // went down this tick
+    pub fn pressed(&self, button: Button) -> bool {
+        self.pressed.contains(&button)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presses_last_one_tick() {
+        let mut input = Input::default();
+        input.press(Button::A);
+        assert!(input.pressed(Button::A) && input.held(Button::A));
+
+        input.end_tick();
+        // key repeat doesn't count as another press
+        input.press(Button::A);
+        assert!(!input.pressed(Button::A) && input.held(Button::A));
+
+        input.release(Button::A);
+        input.end_tick();
+        assert!(!input.held(Button::A));
+        input.press(Button::A);
+        assert!(input.pressed(Button::A));
+    }
+}
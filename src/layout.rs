@@ -0,0 +1,279 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+use crate::area::{Area, Direction};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayoutIssue {
+    // a link names an area that doesn't exist
+    Missing { from: String, area: String },
+    // two paths through the neighbor graph put an area in different places
+    Inconsistent { area: String, placed: (i32, i32), other: (i32, i32) },
+    // two areas claim the same tiles, `at` is the top left tile they share
+    Overlap { first: String, second: String, at: (i32, i32) },
+}
+
+impl fmt::Display for LayoutIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutIssue::Missing { from, area } => write!(f, "{} links to {}, which doesn't exist", from, area),
+            LayoutIssue::Inconsistent { area, placed, other } =>
+                write!(f, "{} is at {:?} one way and {:?} another", area, placed, other),
+            LayoutIssue::Overlap { first, second, at } =>
+                write!(f, "{} and {} both cover {:?}", first, second, at),
+        }
+    }
+}
+
+pub struct Placement {
+    pub area: Rc<Area>,
+    // global tile coordinates of the area's (0, 0)
+    pub origin: (i32, i32),
+}
+
+impl Placement {
+    fn contains(&self, x: i32, y: i32) -> bool {
+        self.area.contains(x - self.origin.0, y - self.origin.1)
+    }
+}
+
+// Every area reachable from a root, placed in one global coordinate frame using
+// the NeighborArea offsets. The root's (0, 0) is the global (0, 0).
+pub struct WorldLayout {
+    // in the order they were reached, which is also the priority when areas overlap
+    placements: Vec<Placement>,
+    index: HashMap<String, usize>,
+    issues: Vec<LayoutIssue>,
+}
+
+impl WorldLayout {
+    pub fn build(areas: &HashMap<String, Rc<Area>>, root: &str) -> Option<Self> {
+        let root = areas.get(root)?.clone();
+
+        let mut layout = WorldLayout {
+            placements: vec![],
+            index: HashMap::new(),
+            issues: vec![],
+        };
+        layout.place(root, (0, 0));
+
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+
+        while let Some(i) = queue.pop_front() {
+            let (area, origin) = {
+                let p = &layout.placements[i];
+                (p.area.clone(), p.origin)
+            };
+
+            for &dir in Direction::ALL.iter() {
+                let link = match area.neighbor(dir) {
+                    Some(link) => link,
+                    None => continue,
+                };
+
+                let neighbor = match areas.get(&link.area) {
+                    Some(n) => n.clone(),
+                    None => {
+                        layout.issues.push(LayoutIssue::Missing { from: area.name.clone(), area: link.area.clone() });
+                        continue;
+                    }
+                };
+
+                let (x, y) = area.neighbor_origin(dir, &neighbor).unwrap();
+                let placed_at = (origin.0 + x, origin.1 + y);
+
+                match layout.index.get(&neighbor.name) {
+                    Some(&j) => {
+                        let existing = layout.placements[j].origin;
+                        let issue = LayoutIssue::Inconsistent {
+                            area: neighbor.name.clone(),
+                            placed: existing,
+                            other: placed_at,
+                        };
+                        // links are usually two-way, only report each disagreement once
+                        if existing != placed_at && !layout.issues.iter().any(|i| WorldLayout::same_inconsistency(i, &issue)) {
+                            layout.issues.push(issue);
+                        }
+                    }
+                    None => {
+                        queue.push_back(layout.place(neighbor, placed_at));
+                    }
+                }
+            }
+        }
+
+        layout.find_overlaps();
+        Some(layout)
+    }
+
+    fn same_inconsistency(a: &LayoutIssue, b: &LayoutIssue) -> bool {
+        match (a, b) {
+            (LayoutIssue::Inconsistent { area: a1, placed: p1, other: o1 },
+             LayoutIssue::Inconsistent { area: a2, placed: p2, other: o2 }) =>
+                a1 == a2 && ((p1, o1) == (p2, o2) || (p1, o1) == (o2, p2)),
+            _ => false,
+        }
+    }
+
+    fn place(&mut self, area: Rc<Area>, origin: (i32, i32)) -> usize {
+        self.index.insert(area.name.clone(), self.placements.len());
+        self.placements.push(Placement { area, origin });
+        self.placements.len() - 1
+    }
+
+    fn find_overlaps(&mut self) {
+        use std::cmp::{min, max};
+
+        for (i, a) in self.placements.iter().enumerate() {
+            for b in self.placements.iter().skip(i + 1) {
+                let left = max(a.origin.0, b.origin.0);
+                let top = max(a.origin.1, b.origin.1);
+                let right = min(a.origin.0 + a.area.width as i32, b.origin.0 + b.area.width as i32);
+                let bottom = min(a.origin.1 + a.area.height as i32, b.origin.1 + b.area.height as i32);
+
+                if left < right && top < bottom {
+                    self.issues.push(LayoutIssue::Overlap {
+                        first: a.area.name.clone(),
+                        second: b.area.name.clone(),
+                        at: (left, top),
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn issues(&self) -> &[LayoutIssue] {
+        &self.issues
+    }
+
+    pub fn placements(&self) -> &[Placement] {
+        &self.placements
+    }
+
+    pub fn origin_of(&self, name: &str) -> Option<(i32, i32)> {
+        self.index.get(name).map(|&i| self.placements[i].origin)
+    }
+
+    // The area owning a global tile, and the tile in that area's coordinates.
+    pub fn area_at(&self, x: i32, y: i32) -> Option<(&Rc<Area>, (i32, i32))> {
+        self.placements.iter()
+            .find(|p| p.contains(x, y))
+            .map(|p| (&p.area, (x - p.origin.0, y - p.origin.1)))
+    }
+
+    // 0 wherever there's no area, same as view_slice's padding
+    pub fn tile_at(&self, x: i32, y: i32) -> u32 {
+        match self.area_at(x, y) {
+            Some((area, (lx, ly))) => area.map[ly as usize * area.width + lx as usize],
+            None => 0,
+        }
+    }
+
+    // A width x height map of global tiles starting at (x, y), e.g. for uploading to the renderer.
+    pub fn window(&self, x: i32, y: i32, width: usize, height: usize) -> Area {
+        let mut rows = vec![];
+        for wy in 0..height as i32 {
+            rows.push((0..width as i32).map(|wx| self.tile_at(x + wx, y + wy)).collect());
+        }
+
+        let mut window = Area::from(rows);
+        // Area::from can't tell the width of an empty map
+        window.width = width;
+        window
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn areas(jsons: &[&str]) -> HashMap<String, Rc<Area>> {
+        jsons.iter().map(|json| {
+            let area: Area = serde_json::from_str(json).unwrap();
+            (area.name.clone(), Rc::new(area))
+        }).collect()
+    }
+
+    // a row of three routes east of a town, with a cave north of the last one
+    fn chain() -> HashMap<String, Rc<Area>> {
+        areas(&[
+            r#"{ "east": { "offset": 0, "area": "r1" },
+                 "tilemap": { "width": 2, "height": 2, "map": [1, 1, 1, 1] }, "name": "town" }"#,
+            r#"{ "west": { "offset": 0, "area": "town" }, "east": { "offset": 1, "area": "r2" },
+                 "tilemap": { "width": 2, "height": 2, "map": [2, 2, 2, 2] }, "name": "r1" }"#,
+            r#"{ "west": { "offset": -1, "area": "r1" }, "north": { "offset": 0, "area": "cave" },
+                 "tilemap": { "width": 1, "height": 1, "map": [3] }, "name": "r2" }"#,
+            r#"{ "south": { "offset": 0, "area": "r2" },
+                 "tilemap": { "width": 1, "height": 3, "map": [4, 4, 4] }, "name": "cave" }"#,
+        ])
+    }
+
+    #[test]
+    fn places_areas_more_than_one_hop_away() {
+        let layout = WorldLayout::build(&chain(), "town").unwrap();
+
+        assert!(layout.issues().is_empty());
+        assert_eq!(layout.origin_of("r1"), Some((2, 0)));
+        assert_eq!(layout.origin_of("r2"), Some((4, 1)));
+        assert_eq!(layout.origin_of("cave"), Some((4, -2)));
+
+        assert_eq!(layout.tile_at(0, 0), 1);
+        assert_eq!(layout.tile_at(4, 1), 3);
+        assert_eq!(layout.tile_at(4, -2), 4);
+        assert_eq!(layout.tile_at(5, 1), 0);
+        assert_eq!(layout.area_at(3, 1).map(|(a, p)| (a.name.as_str(), p)), Some(("r1", (1, 1))));
+    }
+
+    #[test]
+    fn same_frame_from_any_root() {
+        let from_town = WorldLayout::build(&chain(), "town").unwrap();
+        let from_cave = WorldLayout::build(&chain(), "cave").unwrap();
+
+        for y in -3..4 {
+            for x in -1..7 {
+                assert_eq!(from_town.tile_at(x, y), from_cave.tile_at(x - 4, y + 2));
+            }
+        }
+    }
+
+    #[test]
+    fn window_pads_with_zero() {
+        let layout = WorldLayout::build(&chain(), "town").unwrap();
+        let window = layout.window(3, 0, 3, 2);
+        assert_eq!((window.width, window.height), (3, 2));
+        assert_eq!(window.map, vec![2, 4, 0, 2, 3, 0]);
+    }
+
+    #[test]
+    fn reports_inconsistent_links() {
+        let areas = areas(&[
+            r#"{ "east": { "offset": 0, "area": "b" },
+                 "tilemap": { "width": 1, "height": 1, "map": [1] }, "name": "a" }"#,
+            r#"{ "west": { "offset": 2, "area": "a" },
+                 "tilemap": { "width": 1, "height": 1, "map": [2] }, "name": "b" }"#,
+        ]);
+        let layout = WorldLayout::build(&areas, "a").unwrap();
+        assert_eq!(layout.issues(), &[LayoutIssue::Inconsistent { area: "a".to_string(), placed: (0, 0), other: (0, 2) }]);
+    }
+
+    #[test]
+    fn reports_overlaps_and_missing() {
+        let areas = areas(&[
+            r#"{ "east": { "offset": 0, "area": "b" }, "south": { "offset": 1, "area": "c" },
+                 "north": { "offset": 0, "area": "nowhere" },
+                 "tilemap": { "width": 2, "height": 1, "map": [1, 1] }, "name": "a" }"#,
+            r#"{ "tilemap": { "width": 1, "height": 3, "map": [2, 2, 2] }, "name": "b" }"#,
+            r#"{ "tilemap": { "width": 2, "height": 1, "map": [3, 3] }, "name": "c" }"#,
+        ]);
+        let layout = WorldLayout::build(&areas, "a").unwrap();
+
+        assert_eq!(layout.issues(), &[
+            LayoutIssue::Missing { from: "a".to_string(), area: "nowhere".to_string() },
+            LayoutIssue::Overlap { first: "c".to_string(), second: "b".to_string(), at: (2, 1) },
+        ]);
+        // the area reached first wins, south is checked before east
+        assert_eq!(layout.tile_at(2, 1), 3);
+    }
+}
@@ -1,4 +1,6 @@
 use std::{
+    collections::HashMap,
+    rc::Rc,
     sync::Arc,
 };
 
@@ -19,11 +21,53 @@ use vulkano::{
 
 use crate::window::WindowThing;
 use crate::area::Area;
+use crate::ambient::{Ambient, Grading, WorldClock};
+use crate::world::World;
+use crate::area::Direction;
+use crate::game::{Game, Player};
+use crate::dialogue::DialogueState;
+use crate::ui::{FrameStyle, FRAME_TILE, tiles_around};
+use crate::menu::{MenuStack, MenuResult, Screen, ScreenKind};
+use crate::input::Button;
+use crate::save::SaveManager;
+use crate::font::BitmapFont;
+use crate::script::ScriptHost;
+use crate::settings::{Settings, OptionItem};
+use crate::audio::{SoundBank, Track};
+use crate::speaker::Speaker;
+use crate::popup::LocationPopup;
 use serde::ser::Serialize;
 
 pub mod window;
 pub mod vg;
 pub mod area;
+pub mod view;
+pub mod post;
+pub mod palette;
+pub mod ambient;
+pub mod world;
+pub mod layout;
+pub mod warp;
+pub mod transition;
+pub mod game;
+pub mod collision;
+pub mod encounter;
+pub mod npc;
+pub mod script;
+pub mod flags;
+pub mod save;
+pub mod font;
+pub mod input;
+pub mod dialogue;
+pub mod ui;
+pub mod menu;
+pub mod settings;
+pub mod audio;
+pub mod speaker;
+pub mod popup;
+pub mod battle;
+#[cfg(test)]
+mod test_util;
 
 #[derive(Default, Debug, Clone)]
 pub struct Vertex {
@@ -31,6 +75,16 @@ pub struct Vertex {
 }
 vulkano::impl_vertex!(Vertex, position);
 
+// UI quad corners, position in view pixels and uv in pixels of the texture (0 frames, 1 font)
+#[derive(Default, Debug, Clone)]
+pub struct UiVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+    texture: f32,
+}
+vulkano::impl_vertex!(UiVertex, position, uv, color, texture);
+
 pub fn get_device_with_queue(physical: PhysicalDevice) -> (Arc<Device>, Arc<Queue>) {
     let queue_family = {
         physical
@@ -58,8 +112,181 @@ pub fn get_device_with_queue(physical: PhysicalDevice) -> (Arc<Device>, Arc<Queu
     (device, queue)
 }
 
-const VIEW_SIZE: [u32; 2] = [240, 160];
-const INTERNAL_SIZE: [u32; 2] = [256, 176];
+// placeholder until areas are loaded from disk
+fn demo_area() -> Area {
+    let mut area = Area::from(
+        vec![
+            vec![4, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+            vec![1, 1, 2, 1, 1, 4, 4, 1, 2, 3,139, 5, 2, 2, 1, 5, 2, 1, 5, 1],
+            vec![2, 3, 1, 5, 4, 1, 3, 3, 3, 1, 5, 5, 1, 3, 4, 5, 2, 1, 5, 2],
+            vec![3, 3, 3, 2, 4, 1, 3, 3, 2, 2, 2, 3, 5, 4, 2, 3, 2, 2, 1, 4],
+            vec![4, 3, 2, 4, 1, 3, 1, 2, 5, 3, 5, 5, 4, 1, 5, 2, 5, 1, 4, 3],
+            vec![5, 5, 5, 1, 5, 3, 2, 3, 5, 5, 3, 5, 1, 4, 2, 1, 4, 4, 4, 5],
+            vec![2, 2, 5, 3, 3, 4, 5, 5, 2, 3, 2, 4, 1, 2, 2, 3, 1, 2, 3, 2],
+            vec![5, 1, 4, 3, 4, 4, 3, 4, 4, 1, 5, 3, 3, 4, 1, 4, 4, 5, 1, 2],
+            vec![1, 5, 1, 3, 4, 2, 1, 1, 5, 5, 3, 2, 4, 4, 1, 4, 4, 1, 1, 1],
+            vec![2, 3, 5, 2, 2, 2, 2, 4, 1, 2, 5, 5, 4, 1, 5, 3, 2, 2, 3, 4],
+            vec![4, 4, 3, 1, 1, 2, 2, 3, 4, 3, 5, 3, 2, 3, 1, 3, 2, 5, 2, 5],
+            vec![5, 3, 2, 4, 5, 1, 1, 1, 4, 4, 5, 5, 4, 2, 1, 4, 3, 1, 1, 4],
+            vec![4, 2, 4, 3, 2, 2, 4, 3, 3, 5, 3, 1, 5, 1, 4, 5, 4, 5, 3, 2],
+            vec![5, 4, 4, 2, 1, 3, 1, 1, 2, 2, 1, 1, 5, 4, 2, 3, 1, 5, 3, 1],
+            vec![5, 1, 3, 5, 3, 3, 2, 4, 2, 3, 2, 5, 4, 5, 1, 5, 3, 3, 1, 3],
+            vec![3, 2, 4, 5, 5, 4, 5, 5, 5, 2, 3, 5, 2, 4, 5, 2, 4, 3, 2, 5],
+            vec![1, 3, 3, 2, 1, 2, 1, 3, 1, 3, 1, 4, 1, 3, 5, 4, 2, 3, 1, 3],
+            vec![4, 4, 3, 3, 4, 1, 3, 3, 5, 4, 4, 3, 3, 5, 4, 1, 5, 1, 4, 5],
+            vec![1, 4, 2, 1, 3, 1, 4, 2, 2, 2, 2, 5, 4, 3, 4, 1, 3, 2, 1, 2],
+            vec![3, 4, 4, 3, 5, 4, 3, 2, 1, 3, 4, 5, 5, 5, 5, 4, 1, 3, 4, 3]
+        ]
+    );
+
+    area.set_tile(2, 1, 0);
+    area.name = "demo".to_string();
+    area
+}
+
+// the message box along the bottom of the screen, with its choices stacked above on the right
+fn draw_dialogue(s_render: &mut s_render::RenderThing, game: &Game, style: FrameStyle) {
+    let d = match game.dialogue() {
+        Some(d) => d,
+        None => return,
+    };
+
+    let black = [0.2, 0.2, 0.25, 1.0];
+    let view = s_render.view().view_size;
+    let font = &game.font;
+    let line = font.line_height as i32;
+    let tile = FRAME_TILE as i32;
+
+    let rows = tiles_around(game.dialogue_config.lines as u32 * font.line_height);
+    let top = view[1] as i32 - rows as i32 * tile;
+    s_render.draw_frame((0, top), view[0] / FRAME_TILE, rows, style);
+
+    let page = font.layout_lines(d.page());
+    let typed: Vec<_> = page.quads.iter().filter(|q| q.index < d.revealed()).cloned().collect();
+    let text = (tile, top + tile + 2);
+    s_render.draw_text(&typed, text, black);
+
+    if d.arrow_visible() {
+        let last = d.page().last().map_or(0, |l| font.measure(l)) as i32;
+        let arrow = font.layout("▼", view[0]);
+        s_render.draw_text(&arrow.quads, (text.0 + last + 2, text.1 + (d.page().len() as i32 - 1) * line), black);
+    }
+
+    if d.state() == DialogueState::Choosing {
+        draw_list(s_render, font, d.options(), d.cursor(), (view[0] as i32, top), true, style);
+    }
+}
+
+// the area's name on a sign that hangs down from the top left corner
+fn draw_popup(s_render: &mut s_render::RenderThing, popup: &LocationPopup, font: &BitmapFont, style: FrameStyle) {
+    let name = match popup.name() {
+        Some(name) => name,
+        None => return,
+    };
+
+    let black = [0.2, 0.2, 0.25, 1.0];
+    let view = s_render.view().view_size;
+    let tile = FRAME_TILE as i32;
+
+    let text = font.layout(name, view[0] - 2 * FRAME_TILE);
+    let (cols, rows) = (tiles_around(text.width), tiles_around(text.height));
+    let height = rows as i32 * tile;
+    let top = (popup.visible() * height as f32).round() as i32 - height;
+    s_render.draw_frame((0, top), cols, rows, style);
+    s_render.draw_text(&text.quads, (tile, top + tile + 2), black);
+}
+
+// A framed list with a cursor, its right edge at corner.0. Its bottom edge is at
+// corner.1 when `above`, otherwise its top is.
+fn draw_list(s_render: &mut s_render::RenderThing, font: &BitmapFont, items: &[String], cursor: usize, corner: (i32, i32), above: bool, style: FrameStyle) {
+    let black = [0.2, 0.2, 0.25, 1.0];
+    let tile = FRAME_TILE as i32;
+
+    let list = font.layout_lines(items);
+    let arrow = font.layout("▶", 100);
+    let (cols, rows) = (tiles_around(arrow.width + 2 + list.width), tiles_around(list.height));
+    let x = corner.0 - cols as i32 * tile;
+    let y = if above { corner.1 - rows as i32 * tile } else { corner.1 };
+    s_render.draw_frame((x, y), cols, rows, style);
+
+    let text = (x + tile + arrow.width as i32 + 2, y + tile + 2);
+    s_render.draw_text(&list.quads, text, black);
+    s_render.draw_text(&arrow.quads, (x + tile, text.1 + cursor as i32 * font.line_height as i32), black);
+}
+
+// Every screen on the stack, bottom first. Questions get the message box, with
+// their answers above it like a dialogue choice.
+fn draw_menu(s_render: &mut s_render::RenderThing, menu: &MenuStack, font: &BitmapFont, style: FrameStyle) {
+    let black = [0.2, 0.2, 0.25, 1.0];
+    let view = s_render.view().view_size;
+    let tile = FRAME_TILE as i32;
+
+    for (depth, screen) in menu.screens().iter().enumerate() {
+        match &screen.prompt {
+            Some(prompt) => {
+                let lines = font.wrap(prompt, view[0] - 2 * FRAME_TILE);
+                let rows = tiles_around(lines.len() as u32 * font.line_height);
+                let top = view[1] as i32 - rows as i32 * tile;
+                s_render.draw_frame((0, top), view[0] / FRAME_TILE, rows, style);
+                s_render.draw_text(&font.layout_lines(&lines).quads, (tile, top + tile + 2), black);
+
+                draw_list(s_render, font, &screen.menu.items, screen.menu.cursor, (view[0] as i32, top), true, style);
+            }
+            // each screen a little further in than the one it came from
+            None => draw_list(s_render, font, &screen.menu.items, screen.menu.cursor,
+                              (view[0] as i32 - depth as i32 * tile, depth as i32 * tile), false, style),
+        }
+    }
+}
+
+// the screens the pause menu leads to, filled in from the game
+fn menu_screen(kind: ScreenKind, game: &Game, settings: &Settings) -> Screen {
+    match kind {
+        ScreenKind::Bag => {
+            let mut items: Vec<String> = game.items.iter()
+                .map(|(item, count)| format!("{} x{}", item.to_uppercase(), count))
+                .collect();
+            items.sort();
+            items.push("CLOSE BAG".to_string());
+            Screen::new(kind, &items)
+        }
+        ScreenKind::Options => Screen::new(kind, &settings.option_lines()),
+        // nothing to show yet
+        _ => Screen::new(kind, &["BACK"]),
+    }
+}
+
+fn play_area_music(speaker: &Speaker, music: &mut SoundBank<Track>, area: &Area) {
+    let track = area.info.music.as_ref().and_then(|name| music.get(name));
+    speaker.mixer().play_music(track, audio::CROSSFADE);
+}
+
+fn present_mode(settings: &Settings, supported: &swapchain::SupportedPresentModes) -> PresentMode {
+    let mode = match settings.present {
+        settings::PresentMode::Fifo => PresentMode::Fifo,
+        settings::PresentMode::Mailbox => PresentMode::Mailbox,
+        settings::PresentMode::Immediate => PresentMode::Immediate,
+    };
+
+    if supported.supports(mode) {
+        mode
+    } else {
+        println!("{:?} isn't supported here, using vsync", mode);
+        PresentMode::Fifo
+    }
+}
+
+// everything but the present mode, which only applies to new swapchains
+fn apply_settings(settings: &Settings, window_stuff: &mut WindowThing, s_render: &mut s_render::RenderThing, game: &mut Game, speaker: &Speaker) {
+    let view = settings.view();
+    s_render.set_view(view);
+    s_render.set_post(settings.post.settings());
+    speaker.mixer().set_volumes(settings.volume);
+    window_stuff.set_bindings(&settings.keys);
+    window_stuff.set_display(settings.display);
+    window_stuff.set_scale(view.view_size, settings.scale);
+    game.dialogue_config.ticks_per_char = settings.text_speed.ticks_per_char();
+}
 
 fn main() {
     // Initialize Vulkan(o)
@@ -70,10 +297,14 @@ fn main() {
 
     let (device, queue) = get_device_with_queue(physical);
 
+    let settings_path = settings::settings_path();
+    let mut settings = Settings::load(&settings_path);
+
+    let view = settings.view();
 
 
     // Initialize the window + surface
-    let window_stuff = WindowThing::init_window(instance.clone(), VIEW_SIZE);
+    let mut window_stuff = WindowThing::init_window(instance.clone(), view.view_size);
     let surface = window_stuff.surface.clone();
 
 
@@ -94,13 +325,13 @@ fn main() {
             surface.clone(),
             caps.min_image_count,
             format,
-            VIEW_SIZE,
+            view.view_size,
             1,
             caps.supported_usage_flags,
             &queue,
             SurfaceTransform::Identity,
             alpha,
-            PresentMode::Fifo,
+            present_mode(&settings, &caps.present_modes),
             true,
             None,
         )
@@ -130,7 +361,25 @@ fn main() {
     let mut previous_frame_end = Box::new(sync::now(device.clone())) as Box<dyn GpuFuture>;
 
     // this is literally meaningless. rename it whenever.
-    let mut s_render = s_render::new(queue.clone());
+    let mut s_render = s_render::new(queue.clone(), view, swapchain.format());
+    let mut game = {
+        let mut areas = HashMap::new();
+        let demo = Rc::new(demo_area());
+        areas.insert(demo.name.clone(), demo);
+        let world = World::new(areas, "demo").unwrap();
+        Game::new(world, Player { pos: (0, 0), facing: Direction::South, surfing: false }, rand::random())
+    };
+    s_render.set_area(game.world.joined());
+
+    let mut clock = WorldClock::default();
+    let grading = Grading::default();
+    let speaker = Speaker::open();
+    let mut music: SoundBank<Track> = SoundBank::new("res/music");
+    play_area_music(&speaker, &mut music, game.world.current());
+
+    apply_settings(&settings, &mut window_stuff, &mut s_render, &mut game, &speaker);
+    let mut menu = MenuStack::default();
+    let saves = SaveManager::new("saves", 3);
     // why do we get this here?
     let window = surface.window();
 
@@ -185,6 +434,77 @@ fn main() {
                 Err(err) => panic!("{:?}", err),
             };
 
+        let input = &window_stuff.input;
+        if menu.is_open() {
+            // the world stands still while a menu is up
+            match menu.update(input) {
+                MenuResult::Open(kind) => menu.push(menu_screen(kind, &game, &settings)),
+                MenuResult::Selected(ScreenKind::Options, i) => {
+                    match settings.cycle_option(i) {
+                        Some(OptionItem::Present) => println!("vsync changes after a restart"),
+                        Some(_) => {
+                            apply_settings(&settings, &mut window_stuff, &mut s_render, &mut game, &speaker);
+                            // the window (and so the swapchain) may have changed size
+                            recreate_swapchain = true;
+                        }
+                        None => {}
+                    }
+                    if let Err(e) = settings.save(&settings_path) {
+                        println!("failed to save settings to {}: {}", settings_path.display(), e);
+                    }
+
+                    let cursor = menu.top().unwrap().menu.cursor;
+                    menu.pop();
+                    let mut screen = menu_screen(ScreenKind::Options, &game, &settings);
+                    screen.menu.cursor = cursor;
+                    menu.push(screen);
+                }
+                // the placeholder screens only have a way back
+                MenuResult::Selected(ScreenKind::Bag, i) if i + 1 == menu.top().unwrap().menu.items.len() => { menu.pop(); }
+                MenuResult::Selected(ScreenKind::Party, _) => { menu.pop(); }
+                MenuResult::Save => match saves.save(0, &game.save_data()) {
+                    Ok(()) => game.show_text("Your progress was saved."),
+                    Err(e) => {
+                        println!("failed to save: {}", e);
+                        game.show_text("The game couldn't be saved.");
+                    }
+                },
+                MenuResult::Quit => window_stuff.close(),
+                _ => {}
+            }
+        } else {
+            clock.tick();
+
+            if input.pressed(Button::Start) && !game.is_locked() && game.dialogue().is_none() {
+                menu.open_pause();
+            } else {
+                game.handle_input(input);
+            }
+
+            let area_before = game.world.current().name.clone();
+            game.tick();
+            // there's no battle screen yet, so it's just the message
+            if let Some(wild) = game.take_encounter() {
+                game.show_text(&format!("A wild {} appeared!", wild.species.to_uppercase()));
+            }
+            if game.world.current().name != area_before {
+                s_render.set_area(game.world.joined());
+                play_area_music(&speaker, &mut music, game.world.current());
+            }
+        }
+        s_render.set_transition(game.transition().cloned());
+        let area = game.world.current();
+        s_render.set_ambient(Ambient::new(&clock, &grading, area.info.weather(), !area.info.map_type().is_outside()));
+
+        // keep the player in the middle of the view
+        let (px, py) = game.world.to_joined(game.player.pos);
+        let tiles = s_render.view().view_tiles();
+        s_render.set_camera((px - tiles[0] as i32 / 2, py - tiles[1] as i32 / 2));
+
+        draw_popup(&mut s_render, &game.popup, &game.font, settings.frame);
+        draw_dialogue(&mut s_render, &game, settings.frame);
+        draw_menu(&mut s_render, &menu, &game.font, settings.frame);
+
         let command_buffer = s_render.frame(images[image_num].clone());
 
         let future = previous_frame_end.join(acquire_future)
@@ -206,6 +526,7 @@ fn main() {
             }
         }
 
+        window_stuff.input.end_tick();
         window_stuff.handle_input();
 
         if window.read().unwrap().should_close() {
@@ -218,20 +539,27 @@ mod s_render {
     use std::sync::Arc;
     use vulkano::device::Queue;
     use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
-    use crate::{Vertex, INTERNAL_SIZE, VIEW_SIZE};
+    use crate::{Vertex, UiVertex};
+    use crate::view::{ViewConfig, ScaleMode};
+    use crate::post::PostSettings;
+    use crate::palette::{IndexedTileset, Palette, PALETTE_SIZE};
+    use crate::ambient::{Ambient, Weather};
+    use crate::transition::{Transition, TransitionKind};
+    use crate::font::{BitmapFont, GlyphQuad, Spacing};
+    use crate::ui::{FrameStyle, UiList, UiTexture};
     use vulkano::framebuffer::{Subpass, RenderPassAbstract, Framebuffer};
     use vulkano::image::{ImageViewAccess, AttachmentImage, ImageAccess, ImageUsage, ImmutableImage, Dimensions, SwapchainImage};
     use vulkano::format::*;
     use vulkano::command_buffer::{AutoCommandBufferBuilder, AutoCommandBuffer, DynamicState};
-    use vulkano::descriptor::descriptor_set::{PersistentDescriptorSet, FixedSizeDescriptorSet, FixedSizeDescriptorSetsPool};
+    use vulkano::descriptor::descriptor_set::PersistentDescriptorSet;
     use vulkano::pipeline::viewport::Viewport;
-    use vulkano::buffer::{CpuAccessibleBuffer, BufferUsage};
+    use vulkano::buffer::{CpuAccessibleBuffer, ImmutableBuffer, BufferUsage, TypedBufferAccess};
     use vulkano::sync::GpuFuture;
     use vulkano::command_buffer::pool::standard::StandardCommandPoolAlloc;
-    use vulkano::sampler::{Filter, Sampler, UnnormalizedSamplerAddressMode};
+    use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode, UnnormalizedSamplerAddressMode};
     use image::ImageFormat;
     use vulkano::descriptor::{DescriptorSet};
-    use vulkano::pipeline::blend::{AttachmentBlend};
+    use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor, BlendOp};
     use crate::area::Area;
     use crate::vg::WrappedWindow;
 
@@ -240,20 +568,77 @@ mod s_render {
         sampler: Arc<Sampler>,
     }
 
+    // the whole (joined) area lives on the gpu, the shader indexes it with the camera
+    struct AreaBuffer {
+        buffer: Arc<ImmutableBuffer<[i32]>>,
+        set: Arc<dyn DescriptorSet + Send + Sync>,
+        width: i32,
+        height: i32,
+    }
+
+    // tiles stored as palette indices, with swappable palettes
+    struct IndexedBuffer {
+        indices: Arc<dyn ImageViewAccess + Send + Sync>,
+        sampler: Arc<Sampler>,
+        tile_palettes: Arc<ImmutableBuffer<[u32]>>,
+        palettes: Arc<CpuAccessibleBuffer<[[f32; 4]]>>,
+        // palettes per bank, i.e. how many the tileset itself uses
+        bank_size: usize,
+        bank: usize,
+    }
+
     pub struct RenderThing {
         q: Arc<Queue>,
         render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
         pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        view: ViewConfig,
         fbi: Arc<AttachmentImage>,
+        // integer-scaled copy of the view, only used by ScaleMode::SharpBilinear
+        sharp: Option<(u32, Arc<AttachmentImage>)>,
         vbo: Arc<CpuAccessibleBuffer<[Vertex]>>,
-        pool: FixedSizeDescriptorSetsPool<Arc<dyn GraphicsPipelineAbstract + Send + Sync>>,
+        // optional pass drawing fbi onto the swapchain with effects, instead of blitting
+        post: PostSettings,
+        post_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        post_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        post_sampler: Arc<Sampler>,
         ticks: u64,
         bbuf: BootyBuffer,
+        indexed_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        // when set, tiles are drawn from the indexed tileset instead of bbuf
+        indexed: Option<IndexedBuffer>,
+        area: Option<AreaBuffer>,
+        // time of day tint (multiplied) and weather (alpha blended), drawn over the tiles
+        ambient: Ambient,
+        tint_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        weather_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        // drawn over everything else while warping
+        transition: Option<Transition>,
+        transition_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        // screen-fixed windows and text, queued up with draw_frame/draw_text and drawn (then cleared) by the next frame
+        font: BitmapFont,
+        ui: UiList,
+        ui_pipeline: Arc<dyn GraphicsPipelineAbstract + Send + Sync>,
+        ui_set: Arc<dyn DescriptorSet + Send + Sync>,
+        // top-left tile of the view, in joined-area tiles
         camera: (i32, i32),
-        forward: bool,
     }
 
-    pub fn new(q: Arc<Queue>) -> RenderThing {
+    fn create_image(q: &Arc<Queue>, dimensions: [u32; 2]) -> Arc<AttachmentImage> {
+        AttachmentImage::with_usage(
+            q.device().clone(),
+            dimensions,
+            Format::R8G8B8A8Unorm,
+            ImageUsage {
+                transfer_source: true,
+                transfer_destination: true,
+                color_attachment: true,
+                sampled: true,
+                ..ImageUsage::none()
+            }
+        ).unwrap()
+    }
+
+    pub fn new(q: Arc<Queue>, view: ViewConfig, swap_format: Format) -> RenderThing {
         // Creates a
 
         let render_pass = {
@@ -294,16 +679,209 @@ mod s_render {
             )
         };
 
-        let img = {
-            AttachmentImage::with_usage(
+        let indexed_pipeline = {
+            let vs = vs::Shader::load(q.device().clone()).unwrap();
+            let fs = indexed_fs::Shader::load(q.device().clone()).unwrap();
+
+            Arc::new(
+                GraphicsPipeline::start()
+                    .vertex_input_single_buffer::<Vertex>()
+                    .vertex_shader(vs.main_entry_point(), ())
+                    .triangle_strip()
+                    .viewports_dynamic_scissors_irrelevant(1)
+                    .fragment_shader(fs.main_entry_point(), ())
+                    .blend_collective(AttachmentBlend::alpha_blending())
+                    .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                    .build(q.device().clone())
+                    .unwrap(),
+            )
+        };
+
+        let tint_pipeline = {
+            let vs = vs::Shader::load(q.device().clone()).unwrap();
+            let fs = tint_fs::Shader::load(q.device().clone()).unwrap();
+
+            // dst * src, leaving the framebuffer's alpha alone
+            let multiply = AttachmentBlend {
+                enabled: true,
+                color_op: BlendOp::Add,
+                color_source: BlendFactor::DstColor,
+                color_destination: BlendFactor::Zero,
+                alpha_op: BlendOp::Add,
+                alpha_source: BlendFactor::Zero,
+                alpha_destination: BlendFactor::One,
+                mask_red: true,
+                mask_green: true,
+                mask_blue: true,
+                mask_alpha: true,
+            };
+
+            Arc::new(
+                GraphicsPipeline::start()
+                    .vertex_input_single_buffer::<Vertex>()
+                    .vertex_shader(vs.main_entry_point(), ())
+                    .triangle_strip()
+                    .viewports_dynamic_scissors_irrelevant(1)
+                    .fragment_shader(fs.main_entry_point(), ())
+                    .blend_collective(multiply)
+                    .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                    .build(q.device().clone())
+                    .unwrap(),
+            )
+        };
+
+        let weather_pipeline = {
+            let vs = vs::Shader::load(q.device().clone()).unwrap();
+            let fs = weather_fs::Shader::load(q.device().clone()).unwrap();
+
+            Arc::new(
+                GraphicsPipeline::start()
+                    .vertex_input_single_buffer::<Vertex>()
+                    .vertex_shader(vs.main_entry_point(), ())
+                    .triangle_strip()
+                    .viewports_dynamic_scissors_irrelevant(1)
+                    .fragment_shader(fs.main_entry_point(), ())
+                    .blend_collective(AttachmentBlend::alpha_blending())
+                    .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                    .build(q.device().clone())
+                    .unwrap(),
+            )
+        };
+
+        let transition_pipeline = {
+            let vs = vs::Shader::load(q.device().clone()).unwrap();
+            let fs = transition_fs::Shader::load(q.device().clone()).unwrap();
+
+            Arc::new(
+                GraphicsPipeline::start()
+                    .vertex_input_single_buffer::<Vertex>()
+                    .vertex_shader(vs.main_entry_point(), ())
+                    .triangle_strip()
+                    .viewports_dynamic_scissors_irrelevant(1)
+                    .fragment_shader(fs.main_entry_point(), ())
+                    .blend_collective(AttachmentBlend::alpha_blending())
+                    .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                    .build(q.device().clone())
+                    .unwrap(),
+            )
+        };
+
+        let ui_pipeline = {
+            let vs = ui_vs::Shader::load(q.device().clone()).unwrap();
+            let fs = ui_fs::Shader::load(q.device().clone()).unwrap();
+
+            Arc::new(
+                GraphicsPipeline::start()
+                    .vertex_input_single_buffer::<UiVertex>()
+                    .vertex_shader(vs.main_entry_point(), ())
+                    .triangle_list()
+                    .viewports_dynamic_scissors_irrelevant(1)
+                    .fragment_shader(fs.main_entry_point(), ())
+                    .blend_collective(AttachmentBlend::alpha_blending())
+                    .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                    .build(q.device().clone())
+                    .unwrap(),
+            )
+        };
+
+        let font = BitmapFont::builtin(Spacing::Variable);
+
+        let ui_set = {
+            let (frames, frames_future) = {
+                let image = image::load_from_memory_with_format(include_bytes!("../res/frames.png"),
+                                                                ImageFormat::PNG).unwrap().to_rgba();
+                let (width, height) = image.dimensions();
+
+                ImmutableImage::from_iter(
+                    image.into_raw().into_iter(),
+                    Dimensions::Dim2d { width, height },
+                    Format::R8G8B8A8Srgb,
+                    q.clone()
+                ).unwrap()
+            };
+
+            match frames_future.then_signal_fence_and_flush() {
+                Ok(_) => println!("loaded frames"),
+                Err(e) => println!("failed to upload frames: {:?}", e)
+            }
+
+            let size = font.atlas_size();
+            let (atlas, atlas_future) = ImmutableImage::from_iter(
+                font.atlas().iter().cloned(),
+                Dimensions::Dim2d { width: size[0], height: size[1] },
+                Format::R8Unorm,
+                q.clone()
+            ).unwrap();
+
+            match atlas_future.then_signal_fence_and_flush() {
+                Ok(_) => println!("loaded font"),
+                Err(e) => println!("failed to upload font: {:?}", e)
+            }
+
+            let sampler = Sampler::unnormalized(
                 q.device().clone(),
-                INTERNAL_SIZE,
-                Format::R8G8B8A8Unorm,
-                ImageUsage {
-                    transfer_source: true,
-                    color_attachment: true,
-                    ..ImageUsage::none()
-                }
+                Filter::Nearest,
+                UnnormalizedSamplerAddressMode::ClampToEdge,
+                UnnormalizedSamplerAddressMode::ClampToEdge
+            ).unwrap();
+
+            Arc::new(
+                PersistentDescriptorSet::start(ui_pipeline.clone(), 0)
+                    .add_sampled_image(frames, sampler.clone()).unwrap()
+                    .add_sampled_image(atlas, sampler).unwrap()
+                    .build().unwrap()
+            ) as Arc<dyn DescriptorSet + Send + Sync>
+        };
+
+        let img = create_image(&q, view.internal_size());
+
+        let post_pass = {
+            Arc::new(
+                vulkano::single_pass_renderpass!(
+                    q.device().clone(),
+                    attachments: {
+                        output: {
+                            load: Clear,
+                            store: Store,
+                            format: swap_format,
+                            samples: 1,
+                        }
+                    },
+                    pass: {
+                        color: [output],
+                        depth_stencil: {}
+                    }
+                ).unwrap(),
+            )
+        };
+
+        let post_pipeline = {
+            let vs = vs::Shader::load(q.device().clone()).unwrap();
+            let fs = post_fs::Shader::load(q.device().clone()).unwrap();
+
+            Arc::new(
+                GraphicsPipeline::start()
+                    .vertex_input_single_buffer::<Vertex>()
+                    .vertex_shader(vs.main_entry_point(), ())
+                    .triangle_strip()
+                    .viewports_dynamic_scissors_irrelevant(1)
+                    .fragment_shader(fs.main_entry_point(), ())
+                    .render_pass(Subpass::from(post_pass.clone(), 0).unwrap())
+                    .build(q.device().clone())
+                    .unwrap(),
+            )
+        };
+
+        let post_sampler = {
+            Sampler::new(
+                q.device().clone(),
+                Filter::Nearest,
+                Filter::Nearest,
+                MipmapMode::Nearest,
+                SamplerAddressMode::ClampToEdge,
+                SamplerAddressMode::ClampToEdge,
+                SamplerAddressMode::ClampToEdge,
+                0.0, 1.0, 0.0, 0.0
             ).unwrap()
         };
 
@@ -320,8 +898,6 @@ mod s_render {
             ).unwrap()
         };
 
-        let pool : FixedSizeDescriptorSetsPool<Arc<dyn GraphicsPipelineAbstract + Send + Sync>> = FixedSizeDescriptorSetsPool::new(pipeline.clone(), 0);
-
         let bbuf = {
             let (texture, tex_future) = {
                 let image = image::load_from_memory_with_format(include_bytes!("../res/tiles.png"),
@@ -360,87 +936,257 @@ mod s_render {
         RenderThing {
             q,
             render_pass,
-            pool,
             pipeline,
+            view,
             fbi: img,
+            sharp: None,
             vbo,
+            post: PostSettings::default(),
+            post_pass,
+            post_pipeline,
+            post_sampler,
             ticks: 0,
             bbuf,
+            indexed_pipeline,
+            indexed: None,
+            area: None,
+            ambient: Ambient::default(),
+            tint_pipeline,
+            weather_pipeline,
+            transition: None,
+            transition_pipeline,
+            font,
+            ui: UiList::default(),
+            ui_pipeline,
+            ui_set,
             camera: (0, 0),
-            forward: true
         }
     }
 
     impl RenderThing {
-        pub fn frame(&mut self, _swap_img: Arc<SwapchainImage<WrappedWindow>>) -> AutoCommandBuffer<StandardCommandPoolAlloc>
-        {
-            let swap_img1 = Box::new(_swap_img.clone()) as Box<ImageAccess + Send + Sync>;
-            let swap_img2 = Box::new(_swap_img.clone()) as Box<ImageAccess + Send + Sync>;
+        // upload the entire area once (e.g. when the player enters it), instead of slicing it every frame
+        pub fn set_area(&mut self, area: &Area) {
+            let (width, height) = (area.width as i32, area.height as i32);
+
+            let data = [width, height].iter().cloned()
+                .chain(area.map.iter().map(|&t| t as i32))
+                .collect::<Vec<i32>>();
+
+            let (buffer, buf_future) = ImmutableBuffer::from_iter(
+                data.into_iter(),
+                BufferUsage::storage_buffer(),
+                self.q.clone()
+            ).expect("failed to create area buffer");
+
+            match buf_future.then_signal_fence_and_flush() {
+                Ok(_) => println!("uploaded area"),
+                Err(e) => println!("failed to upload area: {:?}", e)
+            }
 
-            self.ticks += 1;
-            let mut offset = ((self.ticks/10)%16) as i32;
+            let set = self.area_set(buffer.clone());
+            self.area = Some(AreaBuffer { buffer, set, width, height });
+        }
+
+        fn area_set(&self, buffer: Arc<ImmutableBuffer<[i32]>>) -> Arc<dyn DescriptorSet + Send + Sync> {
+            match &self.indexed {
+                Some(indexed) => Arc::new(
+                    PersistentDescriptorSet::start(self.indexed_pipeline.clone(), 0)
+                        .add_sampled_image(indexed.indices.clone(), indexed.sampler.clone()).unwrap()
+                        .add_buffer(buffer).unwrap()
+                        .add_buffer(indexed.tile_palettes.clone()).unwrap()
+                        .add_buffer(indexed.palettes.clone()).unwrap()
+                        .build().unwrap()
+                ),
+                None => Arc::new(
+                    PersistentDescriptorSet::start(self.pipeline.clone(), 0)
+                        .add_sampled_image(self.bbuf.texture.clone(), self.bbuf.sampler.clone()).unwrap()
+                        .add_buffer(buffer).unwrap()
+                        .build().unwrap()
+                ),
+            }
+        }
 
-            if !self.forward {
-                offset = 16 - offset;
+        fn rebuild_area_set(&mut self) {
+            if let Some(buffer) = self.area.as_ref().map(|a| a.buffer.clone()) {
+                let set = self.area_set(buffer);
+                self.area.as_mut().unwrap().set = set;
             }
+        }
 
-            let set = {
+        // switch to drawing tiles from palette indices
+        pub fn set_indexed_tileset(&mut self, tileset: &IndexedTileset) {
+            let (indices, tex_future) = ImmutableImage::from_iter(
+                tileset.indices.iter().cloned(),
+                Dimensions::Dim2d { width: tileset.width as u32, height: tileset.height as u32 },
+                Format::R8Uint,
+                self.q.clone()
+            ).unwrap();
+
+            let (tile_palettes, buf_future) = ImmutableBuffer::from_iter(
+                tileset.tile_palettes.iter().cloned(),
+                BufferUsage::storage_buffer(),
+                self.q.clone()
+            ).unwrap();
+
+            match tex_future.join(buf_future).then_signal_fence_and_flush() {
+                Ok(_) => println!("loaded indexed tileset"),
+                Err(e) => println!("failed to load indexed tileset: {:?}", e)
+            }
 
-                let (w, h) = (20, 20);
+            let sampler = Sampler::new(
+                self.q.device().clone(),
+                Filter::Nearest,
+                Filter::Nearest,
+                MipmapMode::Nearest,
+                SamplerAddressMode::ClampToEdge,
+                SamplerAddressMode::ClampToEdge,
+                SamplerAddressMode::ClampToEdge,
+                0.0, 1.0, 0.0, 0.0
+            ).unwrap();
+
+            self.indexed = Some(IndexedBuffer {
+                indices,
+                sampler,
+                tile_palettes,
+                palettes: self.palette_buffer(&tileset.palettes),
+                bank_size: tileset.palettes.len(),
+                bank: 0,
+            });
+            self.rebuild_area_set();
+        }
 
-                if self.ticks % (16*10) == 0 {
-                    let n = if !self.forward { -1 } else { 1 };
-                    self.camera.0 += n;
-                    self.camera.1 += n;
-                }
+        pub fn clear_indexed_tileset(&mut self) {
+            self.indexed = None;
+            self.rebuild_area_set();
+        }
+
+        fn palette_buffer(&self, palettes: &[Palette]) -> Arc<CpuAccessibleBuffer<[[f32; 4]]>> {
+            CpuAccessibleBuffer::from_iter(
+                self.q.device().clone(),
+                BufferUsage::all(),
+                palettes.iter().flat_map(|p| p.to_linear().to_vec().into_iter()),
+            ).expect("failed to create palette buffer")
+        }
+
+        // Replace every palette. Passing several banks' worth (a multiple of the tileset's
+        // palette count) allows switching between them with set_palette_bank.
+        pub fn set_palettes(&mut self, palettes: &[Palette]) {
+            let same_len = match &self.indexed {
+                Some(indexed) => indexed.palettes.len() == palettes.len() * PALETTE_SIZE,
+                None => return,
+            };
 
-                if self.camera.0 + 16 >= w {
-                    self.forward = false;
-                } else if self.camera.0 < 0 {
-                    self.forward = true;
+            if same_len {
+                let indexed = self.indexed.as_ref().unwrap();
+                let mut colors = indexed.palettes.write().unwrap();
+                for (i, p) in palettes.iter().enumerate() {
+                    colors[i*PALETTE_SIZE..(i+1)*PALETTE_SIZE].copy_from_slice(&p.to_linear());
                 }
+            } else {
+                let buffer = self.palette_buffer(palettes);
+                self.indexed.as_mut().unwrap().palettes = buffer;
+                self.rebuild_area_set();
+            }
+        }
 
+        pub fn set_palette_bank(&mut self, bank: usize) {
+            if let Some(indexed) = &mut self.indexed {
+                let banks = indexed.palettes.len() / PALETTE_SIZE / std::cmp::max(1, indexed.bank_size);
+                indexed.bank = std::cmp::min(bank, banks.saturating_sub(1));
+            }
+        }
 
-                let data_buffer = {
-                    let mut _t = Area::from(
-                        vec![
-                            vec![4, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
-                            vec![1, 1, 2, 1, 1, 4, 4, 1, 2, 3,139, 5, 2, 2, 1, 5, 2, 1, 5, 1],
-                            vec![2, 3, 1, 5, 4, 1, 3, 3, 3, 1, 5, 5, 1, 3, 4, 5, 2, 1, 5, 2],
-                            vec![3, 3, 3, 2, 4, 1, 3, 3, 2, 2, 2, 3, 5, 4, 2, 3, 2, 2, 1, 4],
-                            vec![4, 3, 2, 4, 1, 3, 1, 2, 5, 3, 5, 5, 4, 1, 5, 2, 5, 1, 4, 3],
-                            vec![5, 5, 5, 1, 5, 3, 2, 3, 5, 5, 3, 5, 1, 4, 2, 1, 4, 4, 4, 5],
-                            vec![2, 2, 5, 3, 3, 4, 5, 5, 2, 3, 2, 4, 1, 2, 2, 3, 1, 2, 3, 2],
-                            vec![5, 1, 4, 3, 4, 4, 3, 4, 4, 1, 5, 3, 3, 4, 1, 4, 4, 5, 1, 2],
-                            vec![1, 5, 1, 3, 4, 2, 1, 1, 5, 5, 3, 2, 4, 4, 1, 4, 4, 1, 1, 1],
-                            vec![2, 3, 5, 2, 2, 2, 2, 4, 1, 2, 5, 5, 4, 1, 5, 3, 2, 2, 3, 4],
-                            vec![4, 4, 3, 1, 1, 2, 2, 3, 4, 3, 5, 3, 2, 3, 1, 3, 2, 5, 2, 5],
-                            vec![5, 3, 2, 4, 5, 1, 1, 1, 4, 4, 5, 5, 4, 2, 1, 4, 3, 1, 1, 4],
-                            vec![4, 2, 4, 3, 2, 2, 4, 3, 3, 5, 3, 1, 5, 1, 4, 5, 4, 5, 3, 2],
-                            vec![5, 4, 4, 2, 1, 3, 1, 1, 2, 2, 1, 1, 5, 4, 2, 3, 1, 5, 3, 1],
-                            vec![5, 1, 3, 5, 3, 3, 2, 4, 2, 3, 2, 5, 4, 5, 1, 5, 3, 3, 1, 3],
-                            vec![3, 2, 4, 5, 5, 4, 5, 5, 5, 2, 3, 5, 2, 4, 5, 2, 4, 3, 2, 5],
-                            vec![1, 3, 3, 2, 1, 2, 1, 3, 1, 3, 1, 4, 1, 3, 5, 4, 2, 3, 1, 3],
-                            vec![4, 4, 3, 3, 4, 1, 3, 3, 5, 4, 4, 3, 3, 5, 4, 1, 5, 1, 4, 5],
-                            vec![1, 4, 2, 1, 3, 1, 4, 2, 2, 2, 2, 5, 4, 3, 4, 1, 3, 2, 1, 2],
-                            vec![3, 4, 4, 3, 5, 4, 3, 2, 1, 3, 4, 5, 5, 5, 5, 4, 1, 3, 4, 3]
-                        ]
-                    );
-
-                    _t.set_tile(2, 1, 0);
-
-                    let mut b = _t.view_slice(self.camera.0 as usize..(self.camera.0+16) as usize, self.camera.1 as usize..(self.camera.1+11) as usize);
-
-                    CpuAccessibleBuffer::from_iter(self.q.device().clone(), BufferUsage::all(),
-                                                   b.map.to_owned().into_iter()).expect("failed to create buffer")
+        pub fn set_view(&mut self, view: ViewConfig) {
+            if view.view_size != self.view.view_size {
+                self.fbi = create_image(&self.q, view.internal_size());
+            }
+            self.sharp = None;
+            self.view = view;
+        }
+
+        pub fn set_camera(&mut self, camera: (i32, i32)) {
+            self.camera = camera;
+        }
+
+        pub fn view(&self) -> ViewConfig {
+            self.view
+        }
+
+        pub fn set_ambient(&mut self, ambient: Ambient) {
+            self.ambient = ambient;
+        }
+
+        pub fn set_transition(&mut self, transition: Option<Transition>) {
+            self.transition = transition;
+        }
+
+        pub fn font(&self) -> &BitmapFont {
+            &self.font
+        }
+
+        // Queues glyphs (from self.font().layout) for the next frame only, at pos in view pixels.
+        pub fn draw_text(&mut self, quads: &[GlyphQuad], pos: (i32, i32), color: [f32; 4]) {
+            self.ui.text(quads, pos, color);
+        }
+
+        // a window of width by height tiles for the next frame, under anything drawn after it
+        pub fn draw_frame(&mut self, pos: (i32, i32), width: u32, height: u32, style: FrameStyle) {
+            self.ui.frame(pos.0, pos.1, width, height, style);
+        }
+
+        fn ui_vertices(&self) -> Vec<UiVertex> {
+            let mut vertices = Vec::with_capacity(self.ui.quads().len() * 6);
+            for q in self.ui.quads() {
+                let (x0, y0) = (q.x as f32, q.y as f32);
+                let (x1, y1) = (x0 + q.width as f32, y0 + q.height as f32);
+                let (u0, v0) = (q.u as f32, q.v as f32);
+                let (u1, v1) = (u0 + q.width as f32, v0 + q.height as f32);
+                let texture = match q.texture {
+                    UiTexture::Frames => 0.0,
+                    UiTexture::Font => 1.0,
                 };
 
+                let corner = |position, uv| UiVertex { position, uv, color: q.color, texture };
+                vertices.extend_from_slice(&[
+                    corner([x0, y0], [u0, v0]),
+                    corner([x1, y0], [u1, v0]),
+                    corner([x0, y1], [u0, v1]),
+                    corner([x1, y0], [u1, v0]),
+                    corner([x1, y1], [u1, v1]),
+                    corner([x0, y1], [u0, v1]),
+                ]);
+            }
+            vertices
+        }
 
-                self.pool.next()
-                    .add_sampled_image(self.bbuf.texture.clone(), self.bbuf.sampler.clone()).unwrap()
-                    .add_buffer(data_buffer.clone()).unwrap()
-                    .build().unwrap()
-            };
+        pub fn set_post(&mut self, post: PostSettings) {
+            self.post = post.clamped();
+        }
+
+        pub fn post(&self) -> PostSettings {
+            self.post
+        }
+
+        fn sharp_image(&mut self, scale: u32) -> Arc<AttachmentImage> {
+            match &self.sharp {
+                Some((s, img)) if *s == scale => img.clone(),
+                _ => {
+                    let (w, h) = (self.view.view_size[0], self.view.view_size[1]);
+                    let img = create_image(&self.q, [w * scale, h * scale]);
+                    self.sharp = Some((scale, img.clone()));
+                    img
+                }
+            }
+        }
+
+        pub fn frame(&mut self, _swap_img: Arc<SwapchainImage<WrappedWindow>>) -> AutoCommandBuffer<StandardCommandPoolAlloc>
+        {
+            let swap_img1 = Box::new(_swap_img.clone()) as Box<ImageAccess + Send + Sync>;
+            let swap_img2 = Box::new(_swap_img.clone()) as Box<ImageAccess + Send + Sync>;
+
+            self.ticks += 1;
+            // the camera moves a whole tile at a time, so fbi's margin isn't scrolled into yet
+            let offset = 0;
 
             let framebuffer = Arc::new(
                 Framebuffer::start(self.render_pass.clone())
@@ -448,24 +1194,10 @@ mod s_render {
                     .build().unwrap()
             );
 
-            let (dtl, dbr) = {
-                let (w1, h1) = (240i32, 160i32);
-                let (w2, h2) = (swap_img2.dimensions().width() as i32, swap_img2.dimensions().height() as i32);
-
-                let x_scale = w2 / w1;
-                let y_scale = h2 / h1;
-
-                let scale = std::cmp::min(x_scale, y_scale);
-                let (ws, hs) = (w1*scale, h1*scale);
+            let target = [swap_img2.dimensions().width(), swap_img2.dimensions().height()];
+            let (dtl, dbr) = self.view.dest_rect(target);
 
-                let x_offset = (w2-ws)/2;
-                let y_offset = (h2-hs)/2;
-
-
-                ([x_offset, y_offset, 0], [x_offset+ws, y_offset+hs, 1])
-            };
-
-            AutoCommandBufferBuilder::primary_one_time_submit(
+            let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
                 self.q.device().clone(),
                 self.q.family(),
             ).unwrap()
@@ -474,38 +1206,251 @@ mod s_render {
                     false,
                     vec![[1.0, 0.0, 1.0, 1.0].into()],
                 )
-                .unwrap()
-                .draw(
-                    self.pipeline.clone(),
-                    &DynamicState {
-                        viewports: Some(vec![Viewport {
-                            origin: [0.0, 0.0],
-                            dimensions: [INTERNAL_SIZE[0] as f32, INTERNAL_SIZE[1] as f32],
-                            depth_range: 0.0..1.0,
-                        }]),
-                        ..DynamicState::none()
-                    },
+                .unwrap();
+
+            let internal_viewport = DynamicState {
+                viewports: Some(vec![Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [self.view.internal_size()[0] as f32, self.view.internal_size()[1] as f32],
+                    depth_range: 0.0..1.0,
+                }]),
+                ..DynamicState::none()
+            };
+
+            // nothing to draw until an area has been uploaded
+            match (&self.area, &self.indexed) {
+                (Some(area), Some(indexed)) => {
+                    builder = builder.draw(
+                        self.indexed_pipeline.clone(),
+                        &internal_viewport,
+                        vec![self.vbo.clone()],
+                        area.set.clone(),
+                        indexed_fs::ty::Camera {
+                            origin: [self.camera.0, self.camera.1],
+                            palette_offset: (indexed.bank * indexed.bank_size) as i32,
+                        },
+                    )
+                    .unwrap();
+                }
+                (Some(area), None) => {
+                    builder = builder.draw(
+                        self.pipeline.clone(),
+                        &internal_viewport,
+                        vec![self.vbo.clone()],
+                        area.set.clone(),
+                        fs::ty::Camera { origin: [self.camera.0, self.camera.1] },
+                    )
+                    .unwrap();
+                }
+                _ => {}
+            }
+
+            if self.ambient.tint != [1.0, 1.0, 1.0] {
+                let t = self.ambient.tint;
+                builder = builder.draw(
+                    self.tint_pipeline.clone(),
+                    &internal_viewport,
                     vec![self.vbo.clone()],
-                    set,
                     (),
+                    tint_fs::ty::Tint { color: [t[0], t[1], t[2], 1.0] },
                 )
-                .unwrap()
+                .unwrap();
+            }
+
+            let weather = match self.ambient.weather {
+                Weather::Clear => 0,
+                Weather::Rain => 1,
+                Weather::Fog => 2,
+                Weather::Sandstorm => 3,
+            };
+
+            if weather != 0 {
+                // world-space pixel of fbi's corner, so the weather drifts with the map instead of the screen
+                let world = [self.camera.0 * 16, self.camera.1 * 16];
+                builder = builder.draw(
+                    self.weather_pipeline.clone(),
+                    &internal_viewport,
+                    vec![self.vbo.clone()],
+                    (),
+                    weather_fs::ty::Weather {
+                        world,
+                        kind: weather,
+                        ticks: self.ticks as f32,
+                    },
+                )
+                .unwrap();
+            }
+
+            // after the world and its weather, but under transitions so warps fade the UI too
+            if !self.ui.is_empty() {
+                let vertices = CpuAccessibleBuffer::from_iter(
+                    self.q.device().clone(),
+                    BufferUsage::vertex_buffer(),
+                    self.ui_vertices().into_iter(),
+                ).unwrap();
+                self.ui.clear();
+
+                let internal = self.view.internal_size();
+                builder = builder.draw(
+                    self.ui_pipeline.clone(),
+                    &internal_viewport,
+                    vec![vertices],
+                    self.ui_set.clone(),
+                    ui_vs::ty::Screen {
+                        size: [internal[0] as f32, internal[1] as f32],
+                        // the blit only copies fbi from here on, so this keeps the UI still while the world scrolls
+                        origin: [offset as f32, offset as f32],
+                    },
+                )
+                .unwrap();
+            }
+
+            let (vw, vh) = (self.view.view_size[0] as i32, self.view.view_size[1] as i32);
+
+            if let Some(t) = &self.transition {
+                // keep in line with transition_fs
+                let kind = match t.kind {
+                    TransitionKind::None => 0,
+                    TransitionKind::Fade => 1,
+                    TransitionKind::FadeWhite => 2,
+                    TransitionKind::Iris => 3,
+                    TransitionKind::Blinds => 4,
+                    TransitionKind::Swirl => 5,
+                    TransitionKind::Shatter => 6,
+                };
+
+                if kind != 0 {
+                    // the shader works in view pixels, like transition::overlay
+                    builder = builder.draw(
+                        self.transition_pipeline.clone(),
+                        &internal_viewport,
+                        vec![self.vbo.clone()],
+                        (),
+                        transition_fs::ty::Transition {
+                            center: [t.center.0 * vw as f32, t.center.1 * vh as f32],
+                            view_size: [vw as f32, vh as f32],
+                            origin: [offset as f32, offset as f32],
+                            coverage: t.coverage(),
+                            kind,
+                        },
+                    )
+                    .unwrap();
+                }
+            }
+
+            let builder = builder
                 .end_render_pass()
-                .unwrap()
+                .unwrap();
+
+            if self.post.is_active() {
+                let post_set = Arc::new(
+                    PersistentDescriptorSet::start(self.post_pipeline.clone(), 0)
+                        .add_sampled_image(self.fbi.clone(), self.post_sampler.clone()).unwrap()
+                        .build().unwrap()
+                );
+
+                let post_framebuffer = Arc::new(
+                    Framebuffer::start(self.post_pass.clone())
+                        .add(_swap_img.clone()).expect("attach swapchain image failed")
+                        .build().unwrap()
+                );
+
+                let (dw, dh) = ((dbr[0] - dtl[0]) as f32, (dbr[1] - dtl[1]) as f32);
+                let internal = self.view.internal_size();
+                let p = self.post;
+
+                return builder
+                    .begin_render_pass(
+                        post_framebuffer,
+                        false,
+                        vec![[0.0, 0.0, 0.0, 1.0].into()],
+                    )
+                    .unwrap()
+                    .draw(
+                        self.post_pipeline.clone(),
+                        &DynamicState {
+                            viewports: Some(vec![Viewport {
+                                origin: [dtl[0] as f32, dtl[1] as f32],
+                                dimensions: [dw, dh],
+                                depth_range: 0.0..1.0,
+                            }]),
+                            ..DynamicState::none()
+                        },
+                        vec![self.vbo.clone()],
+                        post_set,
+                        post_fs::ty::Post {
+                            dest: [dtl[0] as f32, dtl[1] as f32, dw, dh],
+                            src_offset: [offset as f32, offset as f32],
+                            view_size: [vw as f32, vh as f32],
+                            fbi_size: [internal[0] as f32, internal[1] as f32],
+                            lcd_grid: p.lcd_grid,
+                            scanlines: p.scanlines,
+                            curvature: p.curvature,
+                            bloom: p.bloom,
+                            color_correction: p.color_correction,
+                        },
+                    )
+                    .unwrap()
+                    .end_render_pass()
+                    .unwrap()
+                    .build()
+                    .unwrap();
+            }
+
+            let builder = builder
                 .clear_color_image(swap_img1, ClearValue::Int([0,0,0,1]))
-                .unwrap()
-                .blit_image(
-                    self.fbi.clone(),
-                    [offset, offset, 0],
-                    [VIEW_SIZE[0] as i32 + offset, VIEW_SIZE[1] as i32 + offset, 1],
-                    0, 0,
-                    swap_img2,
-                    dtl,
-                    dbr,
-                    0, 0, 1,
-                    Filter::Nearest
-                )
-                .unwrap()
+                .unwrap();
+
+            let builder = match self.view.scale_mode {
+                ScaleMode::SharpBilinear => {
+                    let scale = self.view.integer_scale(target);
+                    let sharp = self.sharp_image(scale);
+                    let (sw, sh) = (vw * scale as i32, vh * scale as i32);
+
+                    builder
+                        .blit_image(
+                            self.fbi.clone(),
+                            [offset, offset, 0],
+                            [vw + offset, vh + offset, 1],
+                            0, 0,
+                            sharp.clone(),
+                            [0, 0, 0],
+                            [sw, sh, 1],
+                            0, 0, 1,
+                            Filter::Nearest
+                        )
+                        .unwrap()
+                        .blit_image(
+                            sharp,
+                            [0, 0, 0],
+                            [sw, sh, 1],
+                            0, 0,
+                            swap_img2,
+                            dtl,
+                            dbr,
+                            0, 0, 1,
+                            Filter::Linear
+                        )
+                        .unwrap()
+                }
+                _ => {
+                    builder
+                        .blit_image(
+                            self.fbi.clone(),
+                            [offset, offset, 0],
+                            [vw + offset, vh + offset, 1],
+                            0, 0,
+                            swap_img2,
+                            dtl,
+                            dbr,
+                            0, 0, 1,
+                            Filter::Nearest
+                        )
+                        .unwrap()
+                }
+            };
+
+            builder
                 .build()
                 .unwrap()
         }
@@ -538,17 +1483,31 @@ layout(location = 0) out vec4 f_color;
 layout(set = 0, binding = 0) uniform sampler2D tex;
 
 
-layout(set = 0, binding = 1) buffer Data {
-    int grid[11][16];
-} data;
+layout(set = 0, binding = 1) readonly buffer Data {
+    int width;
+    int height;
+    int tiles[];
+} area;
+
+layout(push_constant) uniform Camera {
+    ivec2 origin;
+} camera;
+
+// anything outside the area is tile 0, same as view_slice's padding
+int getTile(in ivec2 idx) {
+    if (idx.x < 0 || idx.y < 0 || idx.x >= area.width || idx.y >= area.height) {
+        return 0;
+    }
+    return area.tiles[idx.y * area.width + idx.x];
+}
 
 vec4 getPixel(in ivec2 pxCoords) {
     ivec2 tSize = ivec2(16);
 
     ivec2 offset = pxCoords % tSize;
-    ivec2 idx = pxCoords / tSize;
+    ivec2 idx = camera.origin + pxCoords / tSize;
 
-    int value = data.grid[idx.y][idx.x];
+    int value = getTile(idx);
 
     int ys = tSize.y * (value/64);
     int xs = tSize.x * (value/64 + value%64);
@@ -560,6 +1519,356 @@ vec4 getPixel(in ivec2 pxCoords) {
 void main() {
     ivec2 pxCoords = ivec2(gl_FragCoord.xy);
     f_color = getPixel(pxCoords);
+}"
+        }
+    }
+
+    mod indexed_fs {
+        vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(location = 0) out vec4 f_color;
+layout(set = 0, binding = 0) uniform usampler2D indices;
+
+layout(set = 0, binding = 1) readonly buffer Data {
+    int width;
+    int height;
+    int tiles[];
+} area;
+
+// which palette every atlas tile uses
+layout(set = 0, binding = 2) readonly buffer TilePalettes {
+    uint tile_palettes[];
+} tp;
+
+// 16 colors per palette, index 0 is transparent
+layout(set = 0, binding = 3) readonly buffer Palettes {
+    vec4 colors[];
+} pal;
+
+layout(push_constant) uniform Camera {
+    ivec2 origin;
+    int palette_offset;
+} camera;
+
+int getTile(in ivec2 idx) {
+    if (idx.x < 0 || idx.y < 0 || idx.x >= area.width || idx.y >= area.height) {
+        return 0;
+    }
+    return area.tiles[idx.y * area.width + idx.x];
+}
+
+vec4 getPixel(in ivec2 pxCoords) {
+    ivec2 tSize = ivec2(16);
+
+    ivec2 offset = pxCoords % tSize;
+    ivec2 idx = camera.origin + pxCoords / tSize;
+
+    int value = getTile(idx);
+
+    int ys = tSize.y * (value/64);
+    int xs = tSize.x * (value/64 + value%64);
+    ivec2 sc = ivec2(xs, ys);
+
+    int tilesPerRow = textureSize(indices, 0).x / tSize.x;
+    int atlasTile = (sc.y / tSize.y) * tilesPerRow + sc.x / tSize.x;
+
+    uint index = texelFetch(indices, sc + offset, 0).r;
+    uint palette = tp.tile_palettes[atlasTile] + uint(camera.palette_offset);
+
+    return pal.colors[palette * 16u + index];
+}
+
+void main() {
+    ivec2 pxCoords = ivec2(gl_FragCoord.xy);
+    f_color = getPixel(pxCoords);
+}"
+        }
+    }
+
+    mod tint_fs {
+        vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(location = 0) out vec4 f_color;
+
+layout(push_constant) uniform Tint {
+    vec4 color;
+} tint;
+
+void main() {
+    f_color = tint.color;
+}"
+        }
+    }
+
+    mod weather_fs {
+        vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(location = 0) out vec4 f_color;
+
+layout(push_constant) uniform Weather {
+    ivec2 world;
+    int kind;
+    float ticks;
+} weather;
+
+float hash(vec2 p) {
+    return fract(sin(dot(p, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+float noise(vec2 p) {
+    vec2 i = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f * f * (3.0 - 2.0 * f);
+    return mix(mix(hash(i), hash(i + vec2(1, 0)), u.x),
+               mix(hash(i + vec2(0, 1)), hash(i + vec2(1, 1)), u.x), u.y);
+}
+
+vec4 rain(vec2 px) {
+    // slanted streaks falling 4px a tick
+    vec2 p = px + vec2(weather.ticks * 2.0, -weather.ticks * 4.0);
+    p.x += p.y * 0.5;
+    vec2 cell = floor(p / vec2(8.0, 24.0));
+    vec2 local = mod(p, vec2(8.0, 24.0));
+    float drop = step(0.8, hash(cell)) * step(local.x, 1.0) * step(local.y, 8.0);
+    return vec4(0.75, 0.8, 1.0, 0.6 * drop);
+}
+
+vec4 fog(vec2 px) {
+    float n = noise(px / 48.0 + vec2(weather.ticks / 400.0, 0.0))
+            + 0.5 * noise(px / 20.0 - vec2(weather.ticks / 250.0, 0.0));
+    return vec4(0.9, 0.9, 0.95, 0.25 + 0.2 * n);
+}
+
+vec4 sandstorm(vec2 px) {
+    vec2 p = px + vec2(-weather.ticks * 3.0, weather.ticks * 0.5);
+    float haze = noise(p / 32.0);
+    float grit = step(0.97, hash(floor(p)));
+    return vec4(0.85, 0.7, 0.45, 0.3 + 0.2 * haze + 0.4 * grit);
+}
+
+void main() {
+    vec2 px = vec2(weather.world) + floor(gl_FragCoord.xy);
+
+    if (weather.kind == 1) {
+        f_color = rain(px);
+    } else if (weather.kind == 2) {
+        f_color = fog(px);
+    } else if (weather.kind == 3) {
+        f_color = sandstorm(px);
+    } else {
+        f_color = vec4(0.0);
+    }
+}"
+        }
+    }
+
+    mod transition_fs {
+        vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(location = 0) out vec4 f_color;
+
+layout(push_constant) uniform Transition {
+    vec2 center;
+    vec2 view_size;
+    // fbi pixel of the view's corner
+    vec2 origin;
+    float coverage;
+    int kind;
+} t;
+
+// the same as transition::overlay, which the golden tests check
+const float BLIND_HEIGHT = 8.0;
+const float SWIRL_ARMS = 4.0;
+const float SHARD_SIZE = 16.0;
+const float PI = 3.14159265;
+
+float shard_hash(uint x, uint y, uint z) {
+    uint n = (x * 73856093u) ^ (y * 19349663u) ^ (z * 83492791u);
+    n = (n ^ (n >> 13)) * 1274126177u;
+    n ^= n >> 16;
+    return float(n & 0xffffu) / 65536.0;
+}
+
+vec4 black(bool covered) {
+    return vec4(0.0, 0.0, 0.0, covered ? 1.0 : 0.0);
+}
+
+void main() {
+    float c = clamp(t.coverage, 0.0, 1.0);
+    vec2 p = floor(gl_FragCoord.xy) + 0.5 - t.origin;
+    vec2 d = p - t.center;
+    float diagonal = length(t.view_size);
+
+    if (t.kind == 1) {
+        f_color = vec4(0.0, 0.0, 0.0, c);
+    } else if (t.kind == 2) {
+        f_color = vec4(1.0, 1.0, 1.0, c);
+    } else if (t.kind == 3) {
+        f_color = black(length(d) >= (1.0 - c) * diagonal);
+    } else if (t.kind == 4) {
+        f_color = black(mod(p.y, BLIND_HEIGHT) < c * BLIND_HEIGHT);
+    } else if (t.kind == 5) {
+        float turn = atan(d.y, d.x) / (2.0 * PI) + 0.5;
+        f_color = black(fract(turn * SWIRL_ARMS + length(d) / diagonal) < c);
+    } else if (t.kind == 6) {
+        vec2 cell = floor(p / SHARD_SIZE);
+        vec2 local = p - cell * SHARD_SIZE;
+        bool half_ = int(cell.x + cell.y) % 2 == 0 ? local.x + local.y < SHARD_SIZE : local.x < local.y;
+        f_color = black(shard_hash(uint(cell.x), uint(cell.y), half_ ? 1u : 0u) < c);
+    } else {
+        f_color = vec4(0.0);
+    }
+}"
+        }
+    }
+
+    mod ui_vs {
+        vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 color;
+layout(location = 3) in float texture;
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+layout(location = 2) flat out int v_texture;
+
+layout(push_constant) uniform Screen {
+    vec2 size;
+    vec2 origin;
+} screen;
+
+void main() {
+    gl_Position = vec4((position + screen.origin) / screen.size * 2.0 - 1.0, 0.0, 1.0);
+    v_uv = uv;
+    v_color = color;
+    v_texture = int(texture);
+}"
+        }
+    }
+
+    mod ui_fs {
+        vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) flat in int v_texture;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D frames;
+layout(set = 0, binding = 1) uniform sampler2D atlas;
+
+void main() {
+    if (v_texture == 0) {
+        f_color = texture(frames, v_uv) * v_color;
+    } else {
+        // the font is only a mask, colored by the vertex
+        if (texture(atlas, v_uv).r < 0.5) {
+            discard;
+        }
+        f_color = v_color;
+    }
+}"
+        }
+    }
+
+    mod post_fs {
+        vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+#version 450
+
+layout(location = 0) out vec4 f_color;
+layout(set = 0, binding = 0) uniform sampler2D fbi;
+
+layout(push_constant) uniform Post {
+    vec4 dest;       // where the view lands on the swapchain, x y w h
+    vec2 src_offset; // sub-tile scroll offset into fbi
+    vec2 view_size;
+    vec2 fbi_size;
+    float lcd_grid;
+    float scanlines;
+    float curvature;
+    float bloom;
+    float color_correction;
+} post;
+
+vec3 fetch(vec2 viewPx) {
+    return texture(fbi, (post.src_offset + viewPx) / post.fbi_size).rgb;
+}
+
+// approximates the gba's lcd, which can't show saturated colors
+vec3 gbaColor(vec3 c) {
+    mat3 profile = mat3(
+        0.80, 0.135, 0.195,
+        0.275, 0.64, 0.155,
+        -0.075, 0.225, 0.65
+    );
+    vec3 screen = pow(c, vec3(2.2 + 0.3));
+    screen = clamp(screen * 0.94, 0.0, 1.0);
+    screen = profile * screen;
+    return pow(clamp(screen, 0.0, 1.0), vec3(1.0 / 2.2));
+}
+
+void main() {
+    vec2 uv = (gl_FragCoord.xy - post.dest.xy) / post.dest.zw;
+
+    if (post.curvature > 0.0) {
+        vec2 cc = uv * 2.0 - 1.0;
+        cc *= 1.0 + post.curvature * 0.25 * dot(cc.yx, cc.yx);
+        uv = cc * 0.5 + 0.5;
+
+        if (any(lessThan(uv, vec2(0.0))) || any(greaterThan(uv, vec2(1.0)))) {
+            f_color = vec4(0.0, 0.0, 0.0, 1.0);
+            return;
+        }
+    }
+
+    vec2 viewPx = uv * post.view_size;
+    vec3 color = fetch(viewPx);
+
+    if (post.bloom > 0.0) {
+        vec3 glow = fetch(viewPx + vec2(1, 0)) + fetch(viewPx - vec2(1, 0))
+                  + fetch(viewPx + vec2(0, 1)) + fetch(viewPx - vec2(0, 1));
+        color += post.bloom * 0.5 * max(glow * 0.25 - 0.5, 0.0);
+    }
+
+    if (post.color_correction > 0.0) {
+        color = mix(color, gbaColor(color), post.color_correction);
+    }
+
+    // position inside the current view pixel
+    vec2 cell = fract(viewPx);
+
+    if (post.lcd_grid > 0.0) {
+        vec2 edge = smoothstep(0.0, 0.2, cell) * smoothstep(0.0, 0.2, 1.0 - cell);
+        color *= mix(1.0, edge.x * edge.y, post.lcd_grid);
+    }
+
+    if (post.scanlines > 0.0) {
+        float line = 0.5 + 0.5 * cos(cell.y * 6.2831853);
+        color *= mix(1.0, 1.0 - line, post.scanlines);
+    }
+
+    f_color = vec4(clamp(color, 0.0, 1.0), 1.0);
 }"
         }
     }
@@ -0,0 +1,273 @@
+use crate::input::{Button, Input};
+
+// A list of options with a cursor that wraps around at either end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Menu {
+    pub items: Vec<String>,
+    pub cursor: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MenuEvent {
+    None,
+    Selected(usize),
+    Cancelled,
+}
+
+impl Menu {
+    pub fn new<S: AsRef<str>>(items: &[S]) -> Self {
+        Menu {
+            items: items.iter().map(|i| i.as_ref().to_string()).collect(),
+            cursor: 0,
+        }
+    }
+
+    pub fn update(&mut self, input: &Input) -> MenuEvent {
+        let n = self.items.len();
+        if n > 0 {
+            if input.pressed(Button::Up) {
+                self.cursor = (self.cursor + n - 1) % n;
+            }
+            if input.pressed(Button::Down) {
+                self.cursor = (self.cursor + 1) % n;
+            }
+        }
+
+        if input.pressed(Button::A) && n > 0 {
+            MenuEvent::Selected(self.cursor)
+        } else if input.pressed(Button::B) {
+            MenuEvent::Cancelled
+        } else {
+            MenuEvent::None
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScreenKind {
+    Pause,
+    Party,
+    Bag,
+    Options,
+    ConfirmSave,
+    ConfirmQuit,
+}
+
+// One screen on the stack, e.g. the pause menu or the bag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Screen {
+    pub kind: ScreenKind,
+    // shown in the message box while the screen is up, e.g. a question
+    pub prompt: Option<String>,
+    pub menu: Menu,
+}
+
+pub const PAUSE_ITEMS: [&str; 5] = ["POKéMON", "BAG", "SAVE", "OPTIONS", "EXIT"];
+
+impl Screen {
+    pub fn new<S: AsRef<str>>(kind: ScreenKind, items: &[S]) -> Self {
+        Screen { kind, prompt: None, menu: Menu::new(items) }
+    }
+
+    pub fn pause() -> Self {
+        Screen::new(ScreenKind::Pause, &PAUSE_ITEMS)
+    }
+
+    pub fn confirm(kind: ScreenKind, prompt: &str) -> Self {
+        Screen {
+            kind,
+            prompt: Some(prompt.to_string()),
+            menu: Menu::new(&["YES", "NO"]),
+        }
+    }
+}
+
+// What the game has to do about the last input, besides drawing the stack.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MenuResult {
+    None,
+    // fill in and push a screen the stack can't make by itself
+    Open(ScreenKind),
+    // something was picked on a screen the caller pushed
+    Selected(ScreenKind, usize),
+    Save,
+    Quit,
+    // the last screen was popped
+    Closed,
+}
+
+// Screens on top of the game. Only the top one gets input.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MenuStack {
+    screens: Vec<Screen>,
+}
+
+impl MenuStack {
+    pub fn is_open(&self) -> bool {
+        !self.screens.is_empty()
+    }
+
+    pub fn screens(&self) -> &[Screen] {
+        &self.screens
+    }
+
+    pub fn top(&self) -> Option<&Screen> {
+        self.screens.last()
+    }
+
+    pub fn push(&mut self, screen: Screen) {
+        self.screens.push(screen);
+    }
+
+    pub fn pop(&mut self) -> Option<Screen> {
+        self.screens.pop()
+    }
+
+    pub fn close(&mut self) {
+        self.screens.clear();
+    }
+
+    pub fn open_pause(&mut self) {
+        if !self.is_open() {
+            self.push(Screen::pause());
+        }
+    }
+
+    pub fn update(&mut self, input: &Input) -> MenuResult {
+        let top = match self.screens.last_mut() {
+            Some(top) => top,
+            None => return MenuResult::None,
+        };
+
+        // start backs all the way out of the pause menu
+        if top.kind == ScreenKind::Pause && input.pressed(Button::Start) {
+            self.close();
+            return MenuResult::Closed;
+        }
+
+        let kind = top.kind;
+        match (kind, top.menu.update(input)) {
+            (_, MenuEvent::None) => MenuResult::None,
+            (_, MenuEvent::Cancelled) => {
+                self.pop();
+                if self.is_open() { MenuResult::None } else { MenuResult::Closed }
+            }
+            (ScreenKind::Pause, MenuEvent::Selected(i)) => match i {
+                0 => MenuResult::Open(ScreenKind::Party),
+                1 => MenuResult::Open(ScreenKind::Bag),
+                2 => {
+                    self.push(Screen::confirm(ScreenKind::ConfirmSave, "Would you like to save the game?"));
+                    MenuResult::None
+                }
+                3 => MenuResult::Open(ScreenKind::Options),
+                _ => {
+                    self.push(Screen::confirm(ScreenKind::ConfirmQuit, "Quit the game?"));
+                    MenuResult::None
+                }
+            },
+            (ScreenKind::ConfirmSave, MenuEvent::Selected(0)) => {
+                self.close();
+                MenuResult::Save
+            }
+            (ScreenKind::ConfirmQuit, MenuEvent::Selected(0)) => MenuResult::Quit,
+            // NO
+            (ScreenKind::ConfirmSave, MenuEvent::Selected(_)) | (ScreenKind::ConfirmQuit, MenuEvent::Selected(_)) => {
+                self.pop();
+                MenuResult::None
+            }
+            (kind, MenuEvent::Selected(i)) => MenuResult::Selected(kind, i),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(stack: &mut MenuStack, button: Button) -> MenuResult {
+        let mut input = Input::default();
+        input.press(button);
+        stack.update(&input)
+    }
+
+    #[test]
+    fn cursor_wraps() {
+        let mut menu = Menu::new(&["a", "b", "c"]);
+        let mut up = Input::default();
+        up.press(Button::Up);
+        menu.update(&up);
+        assert_eq!(menu.cursor, 2);
+
+        let mut down = Input::default();
+        down.press(Button::Down);
+        menu.update(&down);
+        assert_eq!(menu.cursor, 0);
+
+        // nothing to pick in an empty menu
+        let mut a = Input::default();
+        a.press(Button::A);
+        assert_eq!(Menu::new::<&str>(&[]).update(&a), MenuEvent::None);
+    }
+
+    #[test]
+    fn opens_and_closes() {
+        let mut stack = MenuStack::default();
+        assert_eq!(press(&mut stack, Button::A), MenuResult::None);
+
+        stack.open_pause();
+        assert_eq!(stack.top().unwrap().kind, ScreenKind::Pause);
+        assert_eq!(press(&mut stack, Button::Start), MenuResult::Closed);
+        assert!(!stack.is_open());
+
+        stack.open_pause();
+        assert_eq!(press(&mut stack, Button::B), MenuResult::Closed);
+    }
+
+    #[test]
+    fn screens_the_caller_fills_in() {
+        let mut stack = MenuStack::default();
+        stack.open_pause();
+        press(&mut stack, Button::Down);
+        assert_eq!(press(&mut stack, Button::A), MenuResult::Open(ScreenKind::Bag));
+
+        stack.push(Screen::new(ScreenKind::Bag, &["POTION", "POKé BALL"]));
+        // the bag gets the input now, not the pause menu under it
+        press(&mut stack, Button::Down);
+        assert_eq!(stack.screens()[0].menu.cursor, 1);
+        assert_eq!(press(&mut stack, Button::A), MenuResult::Selected(ScreenKind::Bag, 1));
+
+        assert_eq!(press(&mut stack, Button::B), MenuResult::None);
+        assert_eq!(stack.top().unwrap().kind, ScreenKind::Pause);
+    }
+
+    #[test]
+    fn quitting_needs_a_yes() {
+        let mut stack = MenuStack::default();
+        stack.open_pause();
+        press(&mut stack, Button::Up);
+        press(&mut stack, Button::A);
+        assert_eq!(stack.top().unwrap().kind, ScreenKind::ConfirmQuit);
+
+        // start doesn't close the question
+        assert_eq!(press(&mut stack, Button::Start), MenuResult::None);
+        press(&mut stack, Button::Down);
+        assert_eq!(press(&mut stack, Button::A), MenuResult::None);
+        assert_eq!(stack.top().unwrap().kind, ScreenKind::Pause);
+
+        press(&mut stack, Button::A);
+        assert_eq!(press(&mut stack, Button::A), MenuResult::Quit);
+    }
+
+    #[test]
+    fn saving_closes_the_menu() {
+        let mut stack = MenuStack::default();
+        stack.open_pause();
+        press(&mut stack, Button::Down);
+        press(&mut stack, Button::Down);
+        press(&mut stack, Button::A);
+        assert_eq!(stack.top().unwrap().prompt.as_ref().unwrap(), "Would you like to save the game?");
+        assert_eq!(press(&mut stack, Button::A), MenuResult::Save);
+        assert!(!stack.is_open());
+    }
+}
@@ -0,0 +1,239 @@
+use serde::{Serialize, Deserialize};
+use rand::Rng;
+use crate::area::{Area, Direction};
+use crate::collision::{MoveResult, TileRules};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Movement {
+    Static,
+    // random steps, staying within `radius` tiles of where it was placed
+    Wander { radius: u32 },
+    // walks these steps over and over, waiting whenever something is in the way
+    Patrol { path: Vec<Direction> },
+    // turns to random directions without moving
+    LookAround,
+}
+
+impl Default for Movement {
+    fn default() -> Self {
+        Movement::Static
+    }
+}
+
+fn south() -> Direction { Direction::South }
+fn default_interval() -> u32 { 60 }
+
+// An NPC as written in the area file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NpcDef {
+    // unique within the area, for scripts to refer to
+    pub id: String,
+    pub x: i32,
+    pub y: i32,
+    // index into the sprite sheet
+    pub sprite: u32,
+    #[serde(default = "south")]
+    pub facing: Direction,
+    #[serde(default)]
+    pub movement: Movement,
+    // ticks between moves
+    #[serde(default = "default_interval")]
+    pub interval: u32,
+    // run when the player talks to it
+    #[serde(default)]
+    pub script: Option<String>,
+}
+
+// An NPC walking around the current area.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Npc {
+    pub def: NpcDef,
+    pub pos: (i32, i32),
+    pub facing: Direction,
+    path_index: usize,
+    timer: u32,
+}
+
+impl Npc {
+    pub fn spawn(def: &NpcDef) -> Self {
+        Npc {
+            def: def.clone(),
+            pos: (def.x, def.y),
+            facing: def.facing,
+            path_index: 0,
+            timer: 0,
+        }
+    }
+
+    fn home(&self) -> (i32, i32) {
+        (self.def.x, self.def.y)
+    }
+
+    // Only walks on plain ground inside the area, and never onto an occupied tile
+    // (the player or another NPC).
+    pub fn try_step<F: Fn((i32, i32)) -> bool>(&mut self, dir: Direction, area: &Area, rules: &TileRules, occupied: F) -> bool {
+        self.facing = dir;
+        match area.can_move(self.pos, dir, rules) {
+            MoveResult::Walk { to } if !occupied(to) => {
+                self.pos = to;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn update<R: Rng, F: Fn((i32, i32)) -> bool>(&mut self, area: &Area, rules: &TileRules, occupied: F, rng: &mut R) {
+        self.timer += 1;
+        if self.timer < self.def.interval {
+            return;
+        }
+        self.timer = 0;
+
+        match self.def.movement.clone() {
+            Movement::Static => {}
+            Movement::LookAround => {
+                self.facing = Direction::ALL[rng.gen_range(0, 4)];
+            }
+            Movement::Wander { radius } => {
+                let dir = Direction::ALL[rng.gen_range(0, 4)];
+                let d = dir.delta();
+                let (home, r) = (self.home(), radius as i32);
+                let to = (self.pos.0 + d.0, self.pos.1 + d.1);
+
+                if (to.0 - home.0).abs() <= r && (to.1 - home.1).abs() <= r {
+                    self.try_step(dir, area, rules, occupied);
+                } else {
+                    self.facing = dir;
+                }
+            }
+            Movement::Patrol { path } => {
+                if path.is_empty() {
+                    return;
+                }
+                if self.try_step(path[self.path_index], area, rules, occupied) {
+                    self.path_index = (self.path_index + 1) % path.len();
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::collision::TileBehavior;
+
+    // open ground with a rock in the middle
+    fn area() -> Area {
+        Area::from(vec![
+            vec![1, 1, 1, 1, 1],
+            vec![1, 1, 1, 1, 1],
+            vec![1, 1, 2, 1, 1],
+            vec![1, 1, 1, 1, 1],
+            vec![1, 1, 1, 1, 1],
+        ])
+    }
+
+    fn rules() -> TileRules {
+        let mut rules = TileRules::default();
+        rules.tiles.insert(2, TileBehavior::Solid);
+        rules
+    }
+
+    fn npc(json: &str) -> Npc {
+        Npc::spawn(&serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn defaults() {
+        let n = npc(r#"{ "id": "guy", "x": 1, "y": 2, "sprite": 3 }"#);
+        assert_eq!(n.def.movement, Movement::Static);
+        assert_eq!(n.facing, Direction::South);
+        assert_eq!(n.def.interval, 60);
+    }
+
+    #[test]
+    fn patrol_loops() {
+        let mut n = npc(r#"{ "id": "guard", "x": 0, "y": 0, "sprite": 1, "interval": 1,
+                             "movement": { "patrol": { "path": ["east", "east", "west", "west"] } } }"#);
+        let (a, r) = (area(), rules());
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut seen = vec![];
+        for _ in 0..8 {
+            n.update(&a, &r, |_| false, &mut rng);
+            seen.push(n.pos);
+        }
+        assert_eq!(seen, vec![(1, 0), (2, 0), (1, 0), (0, 0), (1, 0), (2, 0), (1, 0), (0, 0)]);
+        assert_eq!(n.facing, Direction::West);
+    }
+
+    #[test]
+    fn patrol_waits_for_the_player() {
+        let mut n = npc(r#"{ "id": "guard", "x": 0, "y": 0, "sprite": 1, "interval": 2,
+                             "movement": { "patrol": { "path": ["east", "west"] } } }"#);
+        let (a, r) = (area(), rules());
+        let mut rng = StdRng::seed_from_u64(0);
+        let player = (1, 0);
+
+        for _ in 0..10 {
+            n.update(&a, &r, |p| p == player, &mut rng);
+        }
+        assert_eq!((n.pos, n.facing), ((0, 0), Direction::East));
+
+        // only moves every other tick
+        n.update(&a, &r, |_| false, &mut rng);
+        assert_eq!(n.pos, (0, 0));
+        n.update(&a, &r, |_| false, &mut rng);
+        assert_eq!(n.pos, (1, 0));
+    }
+
+    #[test]
+    fn patrol_into_a_rock() {
+        let mut n = npc(r#"{ "id": "guard", "x": 2, "y": 1, "sprite": 1, "interval": 1,
+                             "movement": { "patrol": { "path": ["south", "north"] } } }"#);
+        let (a, r) = (area(), rules());
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..5 {
+            n.update(&a, &r, |_| false, &mut rng);
+        }
+        assert_eq!(n.pos, (2, 1));
+    }
+
+    #[test]
+    fn wander_stays_close_and_off_rocks() {
+        let mut n = npc(r#"{ "id": "kid", "x": 1, "y": 2, "sprite": 1, "interval": 1,
+                             "movement": { "wander": { "radius": 1 } } }"#);
+        let (a, r) = (area(), rules());
+        let mut rng = StdRng::seed_from_u64(5);
+
+        let mut moved = false;
+        for _ in 0..500 {
+            n.update(&a, &r, |p| p == (0, 2), &mut rng);
+            assert!(n.pos.0 >= 0 && n.pos.0 <= 2 && n.pos.1 >= 1 && n.pos.1 <= 3, "{:?}", n.pos);
+            assert!(n.pos != (2, 2) && n.pos != (0, 2));
+            moved |= n.pos != (1, 2);
+        }
+        assert!(moved);
+    }
+
+    #[test]
+    fn look_around_stays_put() {
+        let mut n = npc(r#"{ "id": "lookout", "x": 4, "y": 4, "sprite": 1, "interval": 1, "movement": "look_around" }"#);
+        let (a, r) = (area(), rules());
+        let mut rng = StdRng::seed_from_u64(2);
+
+        let mut facings = vec![];
+        for _ in 0..50 {
+            n.update(&a, &r, |_| false, &mut rng);
+            facings.push(n.facing);
+        }
+        assert_eq!(n.pos, (4, 4));
+        assert!(Direction::ALL.iter().all(|d| facings.contains(d)));
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use std::fmt;
+use image::ImageFormat;
+use png::HasParameters;
+
+pub const PALETTE_SIZE: usize = 16;
+pub const TILE_SIZE: usize = 16;
+
+pub type Color = [u8; 4];
+
+// index 0 is always transparent, like on the gba
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub colors: [Color; PALETTE_SIZE],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette { colors: [[0, 0, 0, 0]; PALETTE_SIZE] }
+    }
+}
+
+impl Palette {
+    fn from_colors(colors: &[Color]) -> Self {
+        let mut palette = Palette::default();
+        for (i, c) in colors.iter().enumerate() {
+            palette.colors[i + 1] = *c;
+        }
+        palette
+    }
+
+    // the rgba tileset is sampled as srgb, so palettes are uploaded linear to match
+    pub fn to_linear(&self) -> [[f32; 4]; PALETTE_SIZE] {
+        fn linear(c: u8) -> f32 {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+        }
+
+        let mut out = [[0.0; 4]; PALETTE_SIZE];
+        for (o, c) in out.iter_mut().zip(self.colors.iter()) {
+            *o = [linear(c[0]), linear(c[1]), linear(c[2]), c[3] as f32 / 255.0];
+        }
+        out
+    }
+
+    pub fn tinted(&self, tint: [f32; 3]) -> Self {
+        let mut palette = self.clone();
+        for c in palette.colors.iter_mut().skip(1) {
+            for i in 0..3 {
+                c[i] = (c[i] as f32 * tint[i]).max(0.0).min(255.0) as u8;
+            }
+        }
+        palette
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteError {
+    Decode(String),
+    NotTileAligned { width: usize, height: usize },
+    // the pixels don't add up to width * height
+    WrongLength { expected: usize, len: usize },
+    // a single tile needs more colors than a palette can hold
+    TooManyColors { tile: usize, colors: usize },
+    // a tile in a paletted image uses colors from more than one of its palettes
+    MixedPalettes { tile: usize },
+    // a pixel points past the end of the image's palette
+    IndexOutOfRange { index: usize, colors: usize },
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PaletteError::Decode(e) => write!(f, "couldn't decode tileset: {}", e),
+            PaletteError::NotTileAligned { width, height } =>
+                write!(f, "tileset is {}x{}, which isn't a multiple of {}", width, height, TILE_SIZE),
+            PaletteError::WrongLength { expected, len } =>
+                write!(f, "tileset should have {} bytes of pixels, but has {}", expected, len),
+            PaletteError::TooManyColors { tile, colors } =>
+                write!(f, "tile {} uses {} colors, but a palette only holds {}", tile, colors, PALETTE_SIZE - 1),
+            PaletteError::MixedPalettes { tile } =>
+                write!(f, "tile {} uses colors from more than one palette", tile),
+            PaletteError::IndexOutOfRange { index, colors } =>
+                write!(f, "pixel uses color {}, but the palette only has {}", index, colors),
+        }
+    }
+}
+
+// A tileset stored as palette indices. Every tile picks one palette, so
+// palettes can be swapped at runtime without touching the atlas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexedTileset {
+    pub width: usize,
+    pub height: usize,
+    // one palette index per pixel
+    pub indices: Vec<u8>,
+    // one palette per tile, in atlas order
+    pub tile_palettes: Vec<u32>,
+    pub palettes: Vec<Palette>,
+}
+
+fn check_size(width: usize, height: usize, len: usize, bytes_per_pixel: usize) -> Result<(), PaletteError> {
+    if width % TILE_SIZE != 0 || height % TILE_SIZE != 0 {
+        return Err(PaletteError::NotTileAligned { width, height });
+    }
+    let expected = width * height * bytes_per_pixel;
+    if len != expected {
+        return Err(PaletteError::WrongLength { expected, len });
+    }
+    Ok(())
+}
+
+impl IndexedTileset {
+    // Paletted pngs keep their own palettes (see from_indexed), anything else has
+    // them worked out from its colors.
+    pub fn from_png(bytes: &[u8]) -> Result<Self, PaletteError> {
+        let decode = |e: png::DecodingError| PaletteError::Decode(format!("{}", e));
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set(png::Transformations::IDENTITY);
+        let (info, mut reader) = decoder.read_info().map_err(decode)?;
+        let (width, height) = (info.width as usize, info.height as usize);
+
+        if info.color_type != png::ColorType::Indexed {
+            let image = image::load_from_memory_with_format(bytes, ImageFormat::PNG)
+                .map_err(|e| PaletteError::Decode(format!("{}", e)))?
+                .to_rgba();
+            return IndexedTileset::from_rgba(width, height, &image.into_raw());
+        }
+
+        let mut data = vec![0; info.buffer_size()];
+        reader.next_frame(&mut data).map_err(decode)?;
+
+        // 1, 2 or 4 bit pixels are packed from the high bits down
+        let bits = info.bit_depth as usize;
+        let mask = ((1u16 << bits) - 1) as u8;
+        let mut indices = Vec::with_capacity(width * height);
+        for row in data.chunks(info.line_size).take(height) {
+            for x in 0..width {
+                let bit = x * bits;
+                indices.push(row[bit / 8] >> (8 - bits - bit % 8) & mask);
+            }
+        }
+
+        let png = reader.info();
+        let plte = png.palette.as_ref().map_or(&[][..], |p| &p[..]);
+        let trns = png.trns.as_ref().map_or(&[][..], |t| &t[..]);
+        let colors: Vec<Color> = plte.chunks(3).enumerate()
+            .map(|(i, c)| [c[0], c[1], c[2], trns.get(i).cloned().unwrap_or(255)])
+            .collect();
+
+        IndexedTileset::from_indexed(width, height, &indices, &colors)
+    }
+
+    // For images that come with their palette. It's cut into palettes of 16 the way the
+    // gba does it, so index 17 is color 1 of palette 1. Each tile has to stick to one of
+    // them, and color 0 of every palette is transparent whatever the image says.
+    pub fn from_indexed(width: usize, height: usize, indices: &[u8], colors: &[Color]) -> Result<Self, PaletteError> {
+        check_size(width, height, indices.len(), 1)?;
+
+        let tiles_x = width / TILE_SIZE;
+        let mut tile_palettes = vec![None; tiles_x * (height / TILE_SIZE)];
+        for (i, &index) in indices.iter().enumerate() {
+            let index = index as usize;
+            if index >= colors.len() {
+                return Err(PaletteError::IndexOutOfRange { index, colors: colors.len() });
+            }
+            if index % PALETTE_SIZE == 0 {
+                continue;
+            }
+            let (x, y) = (i % width, i / width);
+            let tile = (y / TILE_SIZE) * tiles_x + x / TILE_SIZE;
+            let palette = (index / PALETTE_SIZE) as u32;
+            match tile_palettes[tile] {
+                Some(p) if p != palette => return Err(PaletteError::MixedPalettes { tile }),
+                _ => tile_palettes[tile] = Some(palette),
+            }
+        }
+
+        let palettes = colors.chunks(PALETTE_SIZE)
+            .map(|c| {
+                let mut palette = Palette::default();
+                palette.colors[1..c.len()].copy_from_slice(&c[1..]);
+                palette
+            })
+            .collect::<Vec<_>>();
+
+        Ok(IndexedTileset {
+            width,
+            height,
+            indices: indices.iter().map(|&i| i % PALETTE_SIZE as u8).collect(),
+            // tiles with nothing opaque in them can use any palette
+            tile_palettes: tile_palettes.iter().map(|p| p.unwrap_or(0)).collect(),
+            palettes: if palettes.is_empty() { vec![Palette::default()] } else { palettes },
+        })
+    }
+
+    // Works the palettes out from the colors used. Index 0 is kept for transparent,
+    // so a tile can only have 15 opaque colors.
+    pub fn from_rgba(width: usize, height: usize, rgba: &[u8]) -> Result<Self, PaletteError> {
+        check_size(width, height, rgba.len(), 4)?;
+
+        let pixel = |x: usize, y: usize| -> Color {
+            let i = (y * width + x) * 4;
+            let c = [rgba[i], rgba[i + 1], rgba[i + 2], rgba[i + 3]];
+            // every fully transparent pixel is the same color
+            if c[3] == 0 { [0, 0, 0, 0] } else { c }
+        };
+
+        let (tiles_x, tiles_y) = (width / TILE_SIZE, height / TILE_SIZE);
+
+        // the opaque colors used by each tile, in order of appearance
+        let mut tile_colors: Vec<Vec<Color>> = vec![];
+        for ty in 0..tiles_y {
+            for tx in 0..tiles_x {
+                let mut colors = vec![];
+                for y in ty*TILE_SIZE..(ty+1)*TILE_SIZE {
+                    for x in tx*TILE_SIZE..(tx+1)*TILE_SIZE {
+                        let c = pixel(x, y);
+                        if c[3] != 0 && !colors.contains(&c) {
+                            colors.push(c);
+                        }
+                    }
+                }
+
+                if colors.len() > PALETTE_SIZE - 1 {
+                    return Err(PaletteError::TooManyColors { tile: tile_colors.len(), colors: colors.len() });
+                }
+                tile_colors.push(colors);
+            }
+        }
+
+        // share palettes between tiles whenever the colors fit
+        let mut palette_colors: Vec<Vec<Color>> = vec![];
+        let mut tile_palettes = vec![];
+        for colors in tile_colors.iter() {
+            let fits = palette_colors.iter().position(|p| {
+                let missing = colors.iter().filter(|c| !p.contains(c)).count();
+                p.len() + missing <= PALETTE_SIZE - 1
+            });
+
+            let index = match fits {
+                Some(i) => i,
+                None => {
+                    palette_colors.push(vec![]);
+                    palette_colors.len() - 1
+                }
+            };
+
+            for c in colors {
+                if !palette_colors[index].contains(c) {
+                    palette_colors[index].push(*c);
+                }
+            }
+            tile_palettes.push(index as u32);
+        }
+
+        let lookup: Vec<HashMap<Color, u8>> = palette_colors.iter()
+            .map(|p| p.iter().enumerate().map(|(i, c)| (*c, i as u8 + 1)).collect())
+            .collect();
+
+        let mut indices = vec![0u8; width * height];
+        for y in 0..height {
+            for x in 0..width {
+                let tile = (y / TILE_SIZE) * tiles_x + x / TILE_SIZE;
+                let c = pixel(x, y);
+                if c[3] != 0 {
+                    indices[y * width + x] = lookup[tile_palettes[tile] as usize][&c];
+                }
+            }
+        }
+
+        Ok(IndexedTileset {
+            width,
+            height,
+            indices,
+            tile_palettes,
+            palettes: palette_colors.iter().map(|p| Palette::from_colors(p)).collect(),
+        })
+    }
+
+    pub fn color_at(&self, x: usize, y: usize) -> Color {
+        let tile = (y / TILE_SIZE) * (self.width / TILE_SIZE) + x / TILE_SIZE;
+        let palette = &self.palettes[self.tile_palettes[tile] as usize];
+        palette.colors[self.indices[y * self.width + x] as usize]
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(tiles_x: usize, tiles_y: usize, color: impl Fn(usize, usize) -> Color) -> Vec<u8> {
+        let (w, h) = (tiles_x * TILE_SIZE, tiles_y * TILE_SIZE);
+        let mut data = vec![];
+        for y in 0..h {
+            for x in 0..w {
+                data.extend_from_slice(&color(x, y));
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn round_trips_colors() {
+        let color = |x: usize, y: usize| -> Color {
+            if (x + y) % 3 == 0 { [0, 0, 0, 0] } else { [(x % 4) as u8 * 60, (y % 3) as u8 * 60, 10, 255] }
+        };
+        let data = image(2, 1, color);
+        let tileset = IndexedTileset::from_rgba(32, 16, &data).unwrap();
+
+        for y in 0..16 {
+            for x in 0..32 {
+                assert_eq!(tileset.color_at(x, y), color(x, y));
+            }
+        }
+        assert_eq!(tileset.indices[0], 0);
+    }
+
+    #[test]
+    fn shares_palettes() {
+        let data = image(2, 2, |x, _| if x < 16 { [255, 0, 0, 255] } else { [0, 0, 255, 255] });
+        let tileset = IndexedTileset::from_rgba(32, 32, &data).unwrap();
+        assert_eq!(tileset.palettes.len(), 1);
+        assert_eq!(tileset.tile_palettes, vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn rejects_too_many_colors() {
+        let data = image(2, 1, |x, y| if x < 16 { [(x + y * 16) as u8, 0, 0, 255] } else { [0, 0, 0, 0] });
+        let err = IndexedTileset::from_rgba(32, 16, &data).unwrap_err();
+        assert_eq!(err, PaletteError::TooManyColors { tile: 0, colors: 256 });
+    }
+
+    #[test]
+    fn splits_palettes_when_full() {
+        // two tiles with 10 different colors each can't share a palette
+        let data = image(2, 1, |x, y| [(x / 16) as u8, ((x + y) % 10) as u8, 0, 255]);
+        let tileset = IndexedTileset::from_rgba(32, 16, &data).unwrap();
+        assert_eq!(tileset.tile_palettes, vec![0, 1]);
+    }
+
+    #[test]
+    fn fifteen_opaque_colors() {
+        // plus transparent makes 16
+        let data = image(1, 1, |x, y| if y == 0 && x == 15 { [0, 0, 0, 0] } else { [(x % 15) as u8, 0, 0, 255] });
+        assert_eq!(IndexedTileset::from_rgba(16, 16, &data).unwrap().palettes.len(), 1);
+
+        let data = image(1, 1, |x, _| [x as u8, 0, 0, 255]);
+        let err = IndexedTileset::from_rgba(16, 16, &data).unwrap_err();
+        assert_eq!(err, PaletteError::TooManyColors { tile: 0, colors: 16 });
+    }
+
+    #[test]
+    fn rejects_unaligned() {
+        assert_eq!(
+            IndexedTileset::from_rgba(10, 16, &vec![0; 10 * 16 * 4]),
+            Err(PaletteError::NotTileAligned { width: 10, height: 16 })
+        );
+        assert_eq!(
+            IndexedTileset::from_rgba(16, 16, &vec![0; 100]),
+            Err(PaletteError::WrongLength { expected: 16 * 16 * 4, len: 100 })
+        );
+    }
+
+    // a paletted png, 4 bit if every index fits and 8 otherwise
+    fn indexed_png(width: u32, height: u32, index: impl Fn(u32, u32) -> u8, colors: &[Color]) -> Vec<u8> {
+        let four = (0..height).all(|y| (0..width).all(|x| index(x, y) < 16));
+        let mut bytes = vec![];
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, width, height);
+            encoder.set(png::ColorType::Indexed).set(if four { png::BitDepth::Four } else { png::BitDepth::Eight });
+            let mut writer = encoder.write_header().unwrap();
+            let plte: Vec<u8> = colors.iter().flat_map(|c| c[..3].to_vec()).collect();
+            let trns: Vec<u8> = colors.iter().map(|c| c[3]).collect();
+            writer.write_chunk(*b"PLTE", &plte).unwrap();
+            writer.write_chunk(*b"tRNS", &trns).unwrap();
+
+            let mut data = vec![];
+            for y in 0..height {
+                for x in (0..width).step_by(if four { 2 } else { 1 }) {
+                    data.push(if four { index(x, y) << 4 | index(x + 1, y) } else { index(x, y) });
+                }
+            }
+            writer.write_image_data(&data).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn keeps_the_png_palette() {
+        // two palettes, the second one only partly filled
+        let colors: Vec<Color> = (0..20).map(|i| [i * 10, 0, 0, if i == 3 { 128 } else { 255 }]).collect();
+        // the left tile uses 3 and 1, the right one 17 and 18 with holes in it
+        let png = indexed_png(32, 16, |x, y| match (x < 16, (x + y) % 3) {
+            (true, 0) => 3,
+            (true, _) => 1,
+            (false, 0) => 16,
+            (false, 1) => 17,
+            (false, _) => 18,
+        }, &colors);
+        let tileset = IndexedTileset::from_png(&png).unwrap();
+
+        assert_eq!(tileset.palettes.len(), 2);
+        assert_eq!(tileset.tile_palettes, vec![0, 1]);
+        // in the same order as the png
+        assert_eq!(tileset.palettes[0].colors[3], [30, 0, 0, 128]);
+        assert_eq!(tileset.palettes[1].colors[2], [180, 0, 0, 255]);
+        assert_eq!(tileset.palettes[1].colors[0], [0, 0, 0, 0]);
+        assert_eq!((tileset.indices[0], tileset.indices[16], tileset.indices[18]), (3, 1, 0));
+        assert_eq!(tileset.color_at(17, 0), [180, 0, 0, 255]);
+
+        // and packed pixels
+        let png = indexed_png(16, 16, |x, _| (x % 4) as u8, &colors[..4]);
+        let tileset = IndexedTileset::from_png(&png).unwrap();
+        assert_eq!(&tileset.indices[..5], &[0, 1, 2, 3, 0]);
+        assert_eq!(tileset.palettes.len(), 1);
+    }
+
+    #[test]
+    fn rejects_mixed_palettes() {
+        let colors = vec![[255, 255, 255, 255]; 32];
+        let png = indexed_png(32, 16, |x, _| if x == 20 { 17 } else if x > 16 { 2 } else { 0 }, &colors);
+        assert_eq!(IndexedTileset::from_png(&png), Err(PaletteError::MixedPalettes { tile: 1 }));
+    }
+
+    #[test]
+    fn rejects_indices_past_the_palette() {
+        let colors = vec![[255, 255, 255, 255]; 16];
+        let png = indexed_png(16, 16, |x, y| if (x, y) == (5, 2) { 20 } else { 1 }, &colors);
+        assert_eq!(IndexedTileset::from_png(&png), Err(PaletteError::IndexOutOfRange { index: 20, colors: 16 }));
+        // even the transparent slot of a palette that isn't there
+        assert_eq!(
+            IndexedTileset::from_indexed(16, 16, &[16; 256], &colors),
+            Err(PaletteError::IndexOutOfRange { index: 16, colors: 16 })
+        );
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+// all in ticks
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PopupConfig {
+    // to slide all the way in, and again to slide out
+    pub slide: u32,
+    pub hold: u32,
+    // before the same name can pop up again
+    pub cooldown: u32,
+}
+
+impl Default for PopupConfig {
+    fn default() -> Self {
+        PopupConfig {
+            slide: 12,
+            hold: 120,
+            cooldown: 300,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PopupPhase {
+    In,
+    Hold,
+    Out,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Banner {
+    name: String,
+    phase: PopupPhase,
+    tick: u32,
+}
+
+// The signpost with the area's name that slides down from the top of the screen
+// when the player walks into somewhere new.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocationPopup {
+    pub config: PopupConfig,
+    banner: Option<Banner>,
+    // display name of where the player is now
+    here: Option<String>,
+    // the tick each name last popped up on
+    last_shown: HashMap<String, u64>,
+    ticks: u64,
+}
+
+impl LocationPopup {
+    pub fn new(config: PopupConfig) -> Self {
+        LocationPopup {
+            config,
+            banner: None,
+            here: None,
+            last_shown: HashMap::new(),
+            ticks: 0,
+        }
+    }
+
+    // Call whenever the player ends up in another area. Nothing pops up between areas
+    // that share a name, or for a name that was up less than a cooldown ago.
+    pub fn enter(&mut self, display_name: &str) -> bool {
+        if self.here.as_ref().map_or(false, |here| here == display_name) {
+            return false;
+        }
+        self.here = Some(display_name.to_string());
+
+        if let Some(&at) = self.last_shown.get(display_name) {
+            if self.ticks - at < self.config.cooldown as u64 {
+                return false;
+            }
+        }
+        self.last_shown.insert(display_name.to_string(), self.ticks);
+
+        // a banner that's already out is swapped without jumping back up
+        let tick = (self.visible() * self.config.slide as f32) as u32;
+        self.banner = Some(Banner { name: display_name.to_string(), phase: PopupPhase::In, tick });
+        true
+    }
+
+    // drops the banner and forgets where the player has been, e.g. after loading a save
+    pub fn reset(&mut self) {
+        *self = LocationPopup::new(self.config);
+    }
+
+    pub fn tick(&mut self) {
+        self.ticks += 1;
+
+        let config = self.config;
+        let finished = match &mut self.banner {
+            Some(b) => {
+                b.tick += 1;
+                let length = if b.phase == PopupPhase::Hold { config.hold } else { config.slide };
+                if b.tick < length {
+                    false
+                } else {
+                    b.tick = 0;
+                    match b.phase {
+                        PopupPhase::In => { b.phase = PopupPhase::Hold; false }
+                        PopupPhase::Hold => { b.phase = PopupPhase::Out; false }
+                        PopupPhase::Out => true,
+                    }
+                }
+            }
+            None => false,
+        };
+
+        if finished {
+            self.banner = None;
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.banner.as_ref().map(|b| b.name.as_str())
+    }
+
+    pub fn phase(&self) -> Option<PopupPhase> {
+        self.banner.as_ref().map(|b| b.phase)
+    }
+
+    // how much of the banner is on screen, from 0 to 1
+    pub fn visible(&self) -> f32 {
+        let b = match &self.banner {
+            Some(b) => b,
+            None => return 0.0,
+        };
+
+        let t = b.tick as f32 / self.config.slide.max(1) as f32;
+        match b.phase {
+            PopupPhase::In => t.min(1.0),
+            PopupPhase::Hold => 1.0,
+            PopupPhase::Out => (1.0 - t).max(0.0),
+        }
+    }
+}
+
+impl Default for LocationPopup {
+    fn default() -> Self {
+        LocationPopup::new(PopupConfig::default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn popup() -> LocationPopup {
+        LocationPopup::new(PopupConfig { slide: 4, hold: 10, cooldown: 50 })
+    }
+
+    fn ticks(p: &mut LocationPopup, n: u32) {
+        for _ in 0..n {
+            p.tick();
+        }
+    }
+
+    #[test]
+    fn slides_in_holds_and_out() {
+        let mut p = popup();
+        assert!(p.enter("PALLET TOWN"));
+        assert_eq!((p.name(), p.visible()), (Some("PALLET TOWN"), 0.0));
+
+        ticks(&mut p, 2);
+        assert_eq!(p.visible(), 0.5);
+        ticks(&mut p, 2);
+        assert_eq!(p.phase(), Some(PopupPhase::Hold));
+        ticks(&mut p, 10);
+        assert_eq!(p.phase(), Some(PopupPhase::Out));
+        ticks(&mut p, 3);
+        assert_eq!(p.visible(), 0.25);
+        ticks(&mut p, 1);
+        assert_eq!(p.name(), None);
+    }
+
+    #[test]
+    fn same_name_stays_quiet() {
+        let mut p = popup();
+        p.enter("VIRIDIAN FOREST");
+        // the forest's two halves share a name
+        assert!(!p.enter("VIRIDIAN FOREST"));
+
+        ticks(&mut p, 100);
+        assert!(!p.enter("VIRIDIAN FOREST"));
+        assert_eq!(p.name(), None);
+    }
+
+    #[test]
+    fn cooldown() {
+        let mut p = popup();
+        p.enter("ROUTE 1");
+        ticks(&mut p, 20);
+        assert!(p.enter("PALLET TOWN"));
+        // stepping straight back over the border
+        ticks(&mut p, 20);
+        assert!(!p.enter("ROUTE 1"));
+
+        ticks(&mut p, 30);
+        p.enter("PALLET TOWN");
+        assert!(p.enter("ROUTE 1"));
+    }
+
+    #[test]
+    fn swapping_banners() {
+        let mut p = popup();
+        p.enter("ROUTE 1");
+        ticks(&mut p, 8);
+        assert!(p.enter("PALLET TOWN"));
+        // still all the way out
+        assert_eq!(p.visible(), 1.0);
+        assert_eq!(p.name(), Some("PALLET TOWN"));
+
+        p.reset();
+        assert_eq!(p.name(), None);
+        assert!(p.enter("PALLET TOWN"));
+    }
+}
@@ -0,0 +1,97 @@
+// Parameters for the optional post-processing pass between the framebuffer and the swapchain.
+// Every strength is 0..1, and a disabled (or all-zero) pass falls back to a plain blit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PostSettings {
+    pub enabled: bool,
+    // darkened gaps between pixels, like the GBA's LCD
+    pub lcd_grid: f32,
+    pub scanlines: f32,
+    // barrel distortion of the whole screen
+    pub curvature: f32,
+    pub bloom: f32,
+    // blend towards the GBA's washed out colors
+    pub color_correction: f32,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        PostSettings {
+            enabled: false,
+            lcd_grid: 0.0,
+            scanlines: 0.0,
+            curvature: 0.0,
+            bloom: 0.0,
+            color_correction: 0.0,
+        }
+    }
+}
+
+impl PostSettings {
+    pub fn gba_lcd() -> Self {
+        PostSettings {
+            enabled: true,
+            lcd_grid: 0.6,
+            color_correction: 1.0,
+            ..PostSettings::default()
+        }
+    }
+
+    pub fn scanlines() -> Self {
+        PostSettings {
+            enabled: true,
+            scanlines: 0.5,
+            ..PostSettings::default()
+        }
+    }
+
+    pub fn crt() -> Self {
+        PostSettings {
+            enabled: true,
+            scanlines: 0.4,
+            curvature: 0.2,
+            bloom: 0.3,
+            ..PostSettings::default()
+        }
+    }
+
+    pub fn clamped(&self) -> Self {
+        let c = |v: f32| if v.is_nan() { 0.0 } else { v.max(0.0).min(1.0) };
+        PostSettings {
+            enabled: self.enabled,
+            lcd_grid: c(self.lcd_grid),
+            scanlines: c(self.scanlines),
+            curvature: c(self.curvature),
+            bloom: c(self.bloom),
+            color_correction: c(self.color_correction),
+        }
+    }
+
+    // whether the post pass has anything to do, otherwise just blit
+    pub fn is_active(&self) -> bool {
+        let s = self.clamped();
+        s.enabled && (s.lcd_grid + s.scanlines + s.curvature + s.bloom + s.color_correction) > 0.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_a_blit() {
+        assert!(!PostSettings::default().is_active());
+        assert!(!PostSettings { enabled: true, ..PostSettings::default() }.is_active());
+        assert!(!PostSettings { enabled: false, ..PostSettings::crt() }.is_active());
+        assert!(PostSettings::gba_lcd().is_active());
+    }
+
+    #[test]
+    fn clamps_strengths() {
+        let s = PostSettings { lcd_grid: 2.0, scanlines: -1.0, bloom: std::f32::NAN, ..PostSettings::crt() }.clamped();
+        assert_eq!(s.lcd_grid, 1.0);
+        assert_eq!(s.scanlines, 0.0);
+        assert_eq!(s.bloom, 0.0);
+        assert_eq!(s.curvature, 0.2);
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::area::Direction;
+use crate::flags::Flags;
+
+// Bump this whenever SaveData changes shape, and add a step to MIGRATIONS that
+// upgrades the previous version's JSON to the new one.
+pub const SAVE_VERSION: u32 = 1;
+
+// MIGRATIONS[i] upgrades a version i + 1 save to version i + 2
+type Migration = fn(&mut Value) -> Result<(), SaveError>;
+const MIGRATIONS: &[Migration] = &[];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SaveError {
+    Parse(String),
+    // saved by a newer build than this one
+    TooNew(u32),
+    // version 0, or no version at all
+    NoVersion,
+    Migration { from: u32, message: String },
+    // nothing saved in the slot yet
+    Empty,
+    Io(String),
+    // the file doesn't start like a save file
+    BadHeader,
+    // shorter than the header says, e.g. the game died mid-write
+    Truncated,
+    // checksum mismatch
+    Corrupt,
+    BadSlot(usize),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Parse(e) => write!(f, "couldn't read save: {}", e),
+            SaveError::TooNew(v) => write!(f, "save is version {}, this build only knows up to {}", v, SAVE_VERSION),
+            SaveError::NoVersion => write!(f, "save has no version"),
+            SaveError::Migration { from, message } => write!(f, "couldn't upgrade save from version {}: {}", from, message),
+            SaveError::Empty => write!(f, "no save in this slot"),
+            SaveError::Io(e) => write!(f, "couldn't access save: {}", e),
+            SaveError::BadHeader => write!(f, "not a save file"),
+            SaveError::Truncated => write!(f, "save file is cut short"),
+            SaveError::Corrupt => write!(f, "save file is corrupted"),
+            SaveError::BadSlot(slot) => write!(f, "no save slot {}", slot),
+        }
+    }
+}
+
+// Everything needed to pick the game back up.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SaveData {
+    pub version: u32,
+    pub area: String,
+    // in the area's tile coordinates
+    pub x: i32,
+    pub y: i32,
+    pub facing: Direction,
+    #[serde(default)]
+    pub surfing: bool,
+    #[serde(default)]
+    pub flags: Flags,
+    #[serde(default)]
+    pub items: BTreeMap<String, u32>,
+}
+
+fn run_migrations(save: &mut Value, migrations: &[Migration], current: u32) -> Result<(), SaveError> {
+    let mut version = save.get("version").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+
+    if version == 0 {
+        return Err(SaveError::NoVersion);
+    }
+    if version > current {
+        return Err(SaveError::TooNew(version));
+    }
+
+    while version < current {
+        let step = migrations.get(version as usize - 1).ok_or(SaveError::Migration {
+            from: version,
+            message: "no migration".to_string(),
+        })?;
+        step(save)?;
+
+        version += 1;
+        save["version"] = Value::from(version);
+    }
+
+    Ok(())
+}
+
+impl SaveData {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    // Upgrades older saves on the way in.
+    pub fn from_json(json: &str) -> Result<SaveData, SaveError> {
+        let mut value: Value = serde_json::from_str(json).map_err(|e| SaveError::Parse(e.to_string()))?;
+        run_migrations(&mut value, MIGRATIONS, SAVE_VERSION)?;
+        serde_json::from_value(value).map_err(|e| SaveError::Parse(e.to_string()))
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => SaveError::Empty,
+            _ => SaveError::Io(e.to_string()),
+        }
+    }
+}
+
+// The file container around the JSON, separate from SAVE_VERSION: a one line header
+//   pkmn-save <FILE_VERSION> <body length> <crc32 of the body, hex>
+// followed by the body.
+const MAGIC: &str = "pkmn-save";
+const FILE_VERSION: u32 = 1;
+
+// plain IEEE crc32, saves are small enough to not bother with a table
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn encode(save: &SaveData) -> Vec<u8> {
+    let body = save.to_json().into_bytes();
+    let mut bytes = format!("{} {} {} {:08x}\n", MAGIC, FILE_VERSION, body.len(), crc32(&body)).into_bytes();
+    bytes.extend(body);
+    bytes
+}
+
+pub fn decode(bytes: &[u8]) -> Result<SaveData, SaveError> {
+    let newline = match bytes.iter().position(|&b| b == b'\n') {
+        Some(i) => i,
+        // a header cut off partway is still recognizable
+        None if !bytes.is_empty() && MAGIC.as_bytes().starts_with(&bytes[..std::cmp::min(bytes.len(), MAGIC.len())]) =>
+            return Err(SaveError::Truncated),
+        None => return Err(SaveError::BadHeader),
+    };
+
+    let header = std::str::from_utf8(&bytes[..newline]).map_err(|_| SaveError::BadHeader)?;
+    let fields: Vec<&str> = header.split(' ').collect();
+    let (len, crc) = match fields.as_slice() {
+        [magic, version, len, crc] if *magic == MAGIC && *version == FILE_VERSION.to_string() => (
+            len.parse::<usize>().map_err(|_| SaveError::BadHeader)?,
+            u32::from_str_radix(crc, 16).map_err(|_| SaveError::BadHeader)?,
+        ),
+        _ => return Err(SaveError::BadHeader),
+    };
+
+    let body = &bytes[newline + 1..];
+    if body.len() < len {
+        return Err(SaveError::Truncated);
+    }
+    if body.len() > len || crc32(body) != crc {
+        return Err(SaveError::Corrupt);
+    }
+
+    let json = std::str::from_utf8(body).map_err(|_| SaveError::Corrupt)?;
+    SaveData::from_json(json)
+}
+
+// Numbered save files in one directory. Every save goes to a temporary file that's
+// renamed over the old one, so a crash mid-save leaves the previous save intact, and
+// the previous save is also kept as a backup in case the new one goes bad later.
+pub struct SaveManager {
+    dir: PathBuf,
+    slots: usize,
+}
+
+impl SaveManager {
+    pub fn new<P: AsRef<Path>>(dir: P, slots: usize) -> Self {
+        SaveManager {
+            dir: dir.as_ref().to_path_buf(),
+            slots,
+        }
+    }
+
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    fn path(&self, slot: usize, ext: &str) -> Result<PathBuf, SaveError> {
+        if slot >= self.slots {
+            return Err(SaveError::BadSlot(slot));
+        }
+        Ok(self.dir.join(format!("slot{}.{}", slot, ext)))
+    }
+
+    pub fn save(&self, slot: usize, save: &SaveData) -> Result<(), SaveError> {
+        let path = self.path(slot, "sav")?;
+        let tmp = self.path(slot, "sav.tmp")?;
+        let backup = self.path(slot, "sav.bak")?;
+
+        fs::create_dir_all(&self.dir)?;
+        {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&encode(save))?;
+            file.sync_all()?;
+        }
+
+        // only a good save is worth backing up, don't let a corrupt one replace the backup
+        if fs::read(&path).ok().map_or(false, |old| decode(&old).is_ok()) {
+            fs::copy(&path, &backup)?;
+        }
+
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    pub fn load(&self, slot: usize) -> Result<SaveData, SaveError> {
+        decode(&fs::read(self.path(slot, "sav")?)?)
+    }
+
+    pub fn load_backup(&self, slot: usize) -> Result<SaveData, SaveError> {
+        decode(&fs::read(self.path(slot, "sav.bak")?)?)
+    }
+
+    // The save, or the backup if the save is unreadable. The bool is whether it's the backup.
+    // Returns the save's error if neither works.
+    pub fn load_or_backup(&self, slot: usize) -> Result<(SaveData, bool), SaveError> {
+        match self.load(slot) {
+            Ok(save) => Ok((save, false)),
+            Err(SaveError::Empty) => Err(SaveError::Empty),
+            Err(e) => self.load_backup(slot).map(|save| (save, true)).map_err(|_| e),
+        }
+    }
+
+    pub fn delete(&self, slot: usize) -> Result<(), SaveError> {
+        for ext in ["sav", "sav.bak", "sav.tmp"].iter() {
+            match fs::remove_file(self.path(slot, ext)?) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+                r => r?,
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn save() -> SaveData {
+        let mut flags = Flags::default();
+        flags.set("got_starter", true);
+        flags.set(&Flags::local("route1", "potion"), true);
+        flags.set_var("badges", 2);
+
+        let mut items = BTreeMap::new();
+        items.insert("potion".to_string(), 3);
+
+        SaveData {
+            version: SAVE_VERSION,
+            area: "route1".to_string(),
+            x: 4,
+            y: -1,
+            facing: Direction::West,
+            surfing: false,
+            flags,
+            items,
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let s = save();
+        assert_eq!(SaveData::from_json(&s.to_json()).unwrap(), s);
+    }
+
+    #[test]
+    fn version_errors() {
+        let mut s = save();
+        s.version = SAVE_VERSION + 1;
+        assert_eq!(SaveData::from_json(&s.to_json()), Err(SaveError::TooNew(SAVE_VERSION + 1)));
+
+        let json = r#"{ "area": "town", "x": 0, "y": 0, "facing": "south" }"#;
+        assert_eq!(SaveData::from_json(json), Err(SaveError::NoVersion));
+
+        match SaveData::from_json("{ not json") {
+            Err(SaveError::Parse(_)) => {}
+            r => panic!("{:?}", r),
+        }
+    }
+
+    // what a future format change would look like: v2 renames x/y to a pos array,
+    // and v3 adds a money field
+    fn v1_to_v2(save: &mut Value) -> Result<(), SaveError> {
+        let fields = save.as_object_mut().unwrap();
+        let x = fields.remove("x").unwrap_or(Value::Null);
+        let y = fields.remove("y").unwrap_or(Value::Null);
+        fields.insert("pos".to_string(), Value::Array(vec![x, y]));
+        Ok(())
+    }
+
+    fn v2_to_v3(save: &mut Value) -> Result<(), SaveError> {
+        save["money"] = Value::from(3000);
+        Ok(())
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let bytes = encode(&save());
+        assert!(bytes.starts_with(b"pkmn-save 1 "));
+        assert_eq!(decode(&bytes).unwrap(), save());
+    }
+
+    #[test]
+    fn damaged_files() {
+        let bytes = encode(&save());
+
+        // cut off anywhere, including inside the header
+        for &len in [0, 4, 12, bytes.len() / 2, bytes.len() - 1].iter() {
+            let err = decode(&bytes[..len]).unwrap_err();
+            assert!(err == SaveError::Truncated || (len == 0 && err == SaveError::BadHeader), "{} {:?}", len, err);
+        }
+
+        // a flipped bit in the body
+        let mut flipped = bytes.clone();
+        let last = flipped.len() - 3;
+        flipped[last] ^= 0x04;
+        assert_eq!(decode(&flipped), Err(SaveError::Corrupt));
+
+        let mut extra = bytes.clone();
+        extra.extend(b"}");
+        assert_eq!(decode(&extra), Err(SaveError::Corrupt));
+
+        assert_eq!(decode(b"{\"version\": 1}\n"), Err(SaveError::BadHeader));
+        assert_eq!(decode(b"pkmn-save 9 2 00000000\n{}"), Err(SaveError::BadHeader));
+        assert_eq!(decode(b"pkmn-save 1 lots 00000000\n{}"), Err(SaveError::BadHeader));
+        assert_eq!(decode(&[0xff, 0xfe, b'\n']), Err(SaveError::BadHeader));
+    }
+
+    #[test]
+    fn slots() {
+        let dir = TempDir::new("save-slots");
+        let saves = SaveManager::new(&dir.0, 3);
+
+        assert_eq!(saves.load(0), Err(SaveError::Empty));
+        assert_eq!(saves.load(3), Err(SaveError::BadSlot(3)));
+
+        let mut other = save();
+        other.area = "town".to_string();
+        saves.save(0, &save()).unwrap();
+        saves.save(2, &other).unwrap();
+
+        assert_eq!(saves.load(0).unwrap(), save());
+        assert_eq!(saves.load(1), Err(SaveError::Empty));
+        assert_eq!(saves.load(2).unwrap(), other);
+        assert!(!dir.0.join("slot0.sav.tmp").exists());
+
+        saves.delete(2).unwrap();
+        assert_eq!(saves.load(2), Err(SaveError::Empty));
+        saves.delete(1).unwrap();
+    }
+
+    #[test]
+    fn backups() {
+        let dir = TempDir::new("save-backups");
+        let saves = SaveManager::new(&dir.0, 1);
+
+        let first = save();
+        let mut second = save();
+        second.x += 1;
+
+        saves.save(0, &first).unwrap();
+        assert_eq!(saves.load_backup(0), Err(SaveError::Empty));
+        saves.save(0, &second).unwrap();
+        assert_eq!(saves.load_backup(0).unwrap(), first);
+
+        // the save gets cut short somehow
+        let path = dir.0.join("slot0.sav");
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+
+        assert_eq!(saves.load(0), Err(SaveError::Truncated));
+        assert_eq!(saves.load_or_backup(0).unwrap(), (first.clone(), true));
+
+        // saving over the broken file keeps the good backup
+        saves.save(0, &second).unwrap();
+        assert_eq!(saves.load_backup(0).unwrap(), first);
+        assert_eq!(saves.load_or_backup(0).unwrap(), (second, false));
+
+        // with both broken, the error is the save's
+        fs::write(&path, b"garbage").unwrap();
+        fs::write(dir.0.join("slot0.sav.bak"), b"").unwrap();
+        assert_eq!(saves.load_or_backup(0), Err(SaveError::BadHeader));
+    }
+
+    #[test]
+    fn migrates_in_order() {
+        let migrations: &[Migration] = &[v1_to_v2, v2_to_v3];
+        let mut value = serde_json::from_str(r#"{ "version": 1, "x": 3, "y": 4 }"#).unwrap();
+
+        run_migrations(&mut value, migrations, 3).unwrap();
+        assert_eq!(value, serde_json::json!({ "version": 3, "pos": [3, 4], "money": 3000 }));
+
+        let mut v2 = serde_json::json!({ "version": 2, "pos": [1, 1] });
+        run_migrations(&mut v2, migrations, 3).unwrap();
+        assert_eq!(v2["money"], 3000);
+
+        let mut missing = serde_json::json!({ "version": 1 });
+        assert_eq!(run_migrations(&mut missing, &[v1_to_v2], 3).unwrap_err(),
+                   SaveError::Migration { from: 2, message: "no migration".to_string() });
+    }
+}
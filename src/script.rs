@@ -0,0 +1,685 @@
+use std::collections::HashMap;
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use crate::area::Direction;
+use crate::warp::TileRect;
+
+// Event scripts, one command per line:
+//
+//   # the professor, before and after you pick a starter
+//   if flag got_starter goto after
+//   text "Take one of these, they're all I've got."
+//   give potion 2
+//   set got_starter
+//   move prof east 2
+//   goto end
+//   label after
+//   text "How's it going?"
+//   label end
+//
+// Flags and variables starting with @ belong to the current area, e.g. `set @potion`
+// on route1 sets route1/potion. Variables are integers, changed with `setvar name 3`
+// and `addvar name -1`, and compared with `if var name >= 3 goto label`.
+//
+// Questions put the index of the answer in a variable, counting from 0:
+//
+//   yesno answer "Want to trade?"      # 0 yes, 1 no
+//   choose starter "Which one?" "BULBASAUR" "CHARMANDER" "SQUIRTLE"
+//   if var starter == 1 goto fire
+//
+// Blocking commands (text, questions, warp, move, wait) pause the script until they're done, so
+// the interpreter is ticked from the game loop instead of running to completion.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Text(String),
+    // shows the text, then stores the picked option's index in var
+    Ask { var: String, text: String, options: Vec<String> },
+    Give { item: String, count: u32 },
+    SetFlag(String),
+    ClearFlag(String),
+    // jump to an instruction when the flag is (or with `not`, isn't) set
+    IfFlag { flag: String, set: bool, target: usize },
+    SetVar { name: String, value: i32 },
+    AddVar { name: String, delta: i32 },
+    IfVar { name: String, op: Compare, value: i32, target: usize },
+    Goto(usize),
+    Move { entity: String, dir: Direction, steps: u32 },
+    Warp { area: String, x: i32, y: i32 },
+    Wait(u32),
+    End,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Compare {
+    fn parse(s: &str) -> Result<Compare, String> {
+        match s {
+            "==" => Ok(Compare::Eq),
+            "!=" => Ok(Compare::Ne),
+            "<" => Ok(Compare::Lt),
+            "<=" => Ok(Compare::Le),
+            ">" => Ok(Compare::Gt),
+            ">=" => Ok(Compare::Ge),
+            _ => Err(format!("unknown comparison {}", s)),
+        }
+    }
+
+    fn test(&self, a: i32, b: i32) -> bool {
+        match self {
+            Compare::Eq => a == b,
+            Compare::Ne => a != b,
+            Compare::Lt => a < b,
+            Compare::Le => a <= b,
+            Compare::Gt => a > b,
+            Compare::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    pub commands: Vec<Command>,
+}
+
+// splits a line on whitespace, keeping "quoted strings" (with \" and \n escapes) together
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => match chars.next() {
+                        Some('n') => s.push('\n'),
+                        Some(e) => s.push(e),
+                        None => return Err("unfinished escape".to_string()),
+                    },
+                    Some(c) => s.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(s);
+        } else {
+            let mut s = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            tokens.push(s);
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn direction(s: &str) -> Result<Direction, String> {
+    match s {
+        "north" => Ok(Direction::North),
+        "south" => Ok(Direction::South),
+        "east" => Ok(Direction::East),
+        "west" => Ok(Direction::West),
+        _ => Err(format!("unknown direction {}", s)),
+    }
+}
+
+fn number<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("expected a number, got {}", s))
+}
+
+// a command before its labels are resolved
+enum Parsed {
+    Command(Command),
+    IfFlag { flag: String, set: bool, label: String },
+    IfVar { name: String, op: Compare, value: i32, label: String },
+    Goto(String),
+}
+
+fn parse_line(tokens: &[String]) -> Result<Parsed, String> {
+    let args: Vec<&str> = tokens.iter().map(|t| t.as_str()).collect();
+
+    let command = match args.as_slice() {
+        ["text", text] => Command::Text(text.to_string()),
+        ["yesno", var, text] => Command::Ask {
+            var: var.to_string(),
+            text: text.to_string(),
+            options: vec!["YES".to_string(), "NO".to_string()],
+        },
+        ["choose", var, text, _, ..] => Command::Ask {
+            var: var.to_string(),
+            text: text.to_string(),
+            options: args[3..].iter().map(|o| o.to_string()).collect(),
+        },
+        ["give", item] => Command::Give { item: item.to_string(), count: 1 },
+        ["give", item, count] => Command::Give { item: item.to_string(), count: number(count)? },
+        ["set", flag] => Command::SetFlag(flag.to_string()),
+        ["clear", flag] => Command::ClearFlag(flag.to_string()),
+        ["if", "flag", flag, "goto", label] =>
+            return Ok(Parsed::IfFlag { flag: flag.to_string(), set: true, label: label.to_string() }),
+        ["if", "not", "flag", flag, "goto", label] =>
+            return Ok(Parsed::IfFlag { flag: flag.to_string(), set: false, label: label.to_string() }),
+        ["if", "var", name, op, value, "goto", label] => return Ok(Parsed::IfVar {
+            name: name.to_string(),
+            op: Compare::parse(op)?,
+            value: number(value)?,
+            label: label.to_string(),
+        }),
+        ["setvar", name, value] => Command::SetVar { name: name.to_string(), value: number(value)? },
+        ["addvar", name, delta] => Command::AddVar { name: name.to_string(), delta: number(delta)? },
+        ["goto", label] => return Ok(Parsed::Goto(label.to_string())),
+        ["move", entity, dir] => Command::Move { entity: entity.to_string(), dir: direction(dir)?, steps: 1 },
+        ["move", entity, dir, steps] => Command::Move { entity: entity.to_string(), dir: direction(dir)?, steps: number(steps)? },
+        ["warp", area, x, y] => Command::Warp { area: area.to_string(), x: number(x)?, y: number(y)? },
+        ["wait", ticks] => Command::Wait(number(ticks)?),
+        ["end"] => Command::End,
+        [name, ..] => return Err(format!("bad arguments for {}", name)),
+        [] => unreachable!(),
+    };
+
+    Ok(Parsed::Command(command))
+}
+
+impl Script {
+    pub fn parse(src: &str) -> Result<Script, ParseError> {
+        let mut parsed = vec![];
+        let mut labels = HashMap::new();
+
+        for (i, line) in src.lines().enumerate() {
+            let err = |message: String| ParseError { line: i + 1, message };
+            let tokens = tokenize(line).map_err(err)?;
+
+            match tokens.first().map(|t| t.as_str()) {
+                None => {}
+                Some("label") if tokens.len() == 2 => {
+                    if labels.insert(tokens[1].clone(), parsed.len()).is_some() {
+                        return Err(err(format!("duplicate label {}", tokens[1])));
+                    }
+                }
+                Some(_) => parsed.push((i + 1, parse_line(&tokens).map_err(err)?)),
+            }
+        }
+
+        let mut commands = vec![];
+        for (line, p) in parsed {
+            let resolve = |label: &str| labels.get(label).cloned()
+                .ok_or(ParseError { line, message: format!("unknown label {}", label) });
+
+            commands.push(match p {
+                Parsed::Command(c) => c,
+                Parsed::IfFlag { flag, set, label } => Command::IfFlag { flag, set, target: resolve(&label)? },
+                Parsed::IfVar { name, op, value, label } => Command::IfVar { name, op, value, target: resolve(&label)? },
+                Parsed::Goto(label) => Command::Goto(resolve(&label)?),
+            });
+        }
+
+        Ok(Script { commands })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TriggerKind {
+    // walking onto it
+    Step,
+    // facing it and pressing the button, e.g. a sign
+    Interact,
+}
+
+impl Default for TriggerKind {
+    fn default() -> Self {
+        TriggerKind::Step
+    }
+}
+
+// Runs a script when the player steps on (or interacts with) part of an area.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Trigger {
+    pub rect: TileRect,
+    pub script: String,
+    #[serde(default)]
+    pub on: TriggerKind,
+}
+
+// Scripts by id, which is how areas refer to them.
+#[derive(Debug, Clone, Default)]
+pub struct ScriptLibrary {
+    scripts: HashMap<String, Script>,
+}
+
+impl ScriptLibrary {
+    pub fn add(&mut self, id: &str, src: &str) -> Result<(), ParseError> {
+        self.scripts.insert(id.to_string(), Script::parse(src)?);
+        Ok(())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Script> {
+        self.scripts.get(id)
+    }
+}
+
+// Whatever the script is acting on, e.g. the game, or a fake one in tests.
+pub trait ScriptHost {
+    fn flag(&self, name: &str) -> bool;
+    fn set_flag(&mut self, name: &str, value: bool);
+    fn var(&self, name: &str) -> i32;
+    fn set_var(&mut self, name: &str, value: i32);
+    fn give_item(&mut self, item: &str, count: u32);
+    fn show_text(&mut self, text: &str);
+    // like show_text, with a choice at the end
+    fn ask(&mut self, text: &str, options: &[String]);
+    // the last ask's answer, once it's been picked
+    fn take_answer(&mut self) -> Option<usize>;
+    // take one step, false if something is in the way
+    fn move_entity(&mut self, entity: &str, dir: Direction) -> bool;
+    fn warp(&mut self, area: &str, x: i32, y: i32);
+    // text still on screen, a transition running...
+    fn busy(&self) -> bool;
+}
+
+// most commands a script may run in one tick, so a goto loop can't hang the game
+const MAX_COMMANDS_PER_TICK: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptVm {
+    script: Script,
+    pc: usize,
+    // ticks left on a wait
+    waiting: u32,
+    // steps left on a move
+    moving: u32,
+    // waiting on the answer to an Ask
+    asking: bool,
+    done: bool,
+}
+
+impl ScriptVm {
+    pub fn new(script: Script) -> Self {
+        ScriptVm {
+            script,
+            pc: 0,
+            waiting: 0,
+            moving: 0,
+            asking: false,
+            done: false,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    // Runs until the script blocks or ends. Returns true once it has ended.
+    pub fn tick<H: ScriptHost>(&mut self, host: &mut H) -> bool {
+        for _ in 0..MAX_COMMANDS_PER_TICK {
+            if self.done {
+                break;
+            }
+            if host.busy() {
+                return false;
+            }
+            if self.waiting > 0 {
+                self.waiting -= 1;
+                if self.waiting > 0 {
+                    return false;
+                }
+                self.pc += 1;
+                continue;
+            }
+
+            let command = match self.script.commands.get(self.pc) {
+                Some(c) => c.clone(),
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+
+            match command {
+                Command::Text(text) => {
+                    host.show_text(&text);
+                    self.pc += 1;
+                }
+                Command::Ask { var, text, options } => {
+                    if self.asking {
+                        // closed without picking anything, e.g. by a warp, counts as the last option
+                        let answer = host.take_answer().unwrap_or(options.len() - 1);
+                        host.set_var(&var, answer as i32);
+                        self.asking = false;
+                        self.pc += 1;
+                    } else {
+                        host.ask(&text, &options);
+                        self.asking = true;
+                    }
+                }
+                Command::Give { item, count } => {
+                    host.give_item(&item, count);
+                    self.pc += 1;
+                }
+                Command::SetFlag(flag) => {
+                    host.set_flag(&flag, true);
+                    self.pc += 1;
+                }
+                Command::ClearFlag(flag) => {
+                    host.set_flag(&flag, false);
+                    self.pc += 1;
+                }
+                Command::IfFlag { flag, set, target } => {
+                    self.pc = if host.flag(&flag) == set { target } else { self.pc + 1 };
+                }
+                Command::SetVar { name, value } => {
+                    host.set_var(&name, value);
+                    self.pc += 1;
+                }
+                Command::AddVar { name, delta } => {
+                    let value = host.var(&name).saturating_add(delta);
+                    host.set_var(&name, value);
+                    self.pc += 1;
+                }
+                Command::IfVar { name, op, value, target } => {
+                    self.pc = if op.test(host.var(&name), value) { target } else { self.pc + 1 };
+                }
+                Command::Goto(target) => {
+                    self.pc = target;
+                }
+                Command::Move { entity, dir, steps } => {
+                    if self.moving == 0 {
+                        self.moving = steps;
+                    }
+                    // one step a tick, trying again next tick when blocked
+                    if self.moving > 0 && host.move_entity(&entity, dir) {
+                        self.moving -= 1;
+                    }
+                    if self.moving == 0 {
+                        self.pc += 1;
+                    }
+                    return false;
+                }
+                Command::Warp { area, x, y } => {
+                    host.warp(&area, x, y);
+                    self.pc += 1;
+                }
+                Command::Wait(ticks) => {
+                    if ticks == 0 {
+                        self.pc += 1;
+                    } else {
+                        self.waiting = ticks;
+                        return false;
+                    }
+                }
+                Command::End => {
+                    self.done = true;
+                }
+            }
+        }
+
+        self.done
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet};
+
+    #[derive(Default)]
+    struct Host {
+        flags: HashSet<String>,
+        vars: HashMap<String, i32>,
+        items: Vec<(String, u32)>,
+        log: Vec<String>,
+        text_open: bool,
+        blocked_moves: u32,
+        // picked by the "player" for every question
+        answer: Option<usize>,
+    }
+
+    impl ScriptHost for Host {
+        fn flag(&self, name: &str) -> bool {
+            self.flags.contains(name)
+        }
+
+        fn set_flag(&mut self, name: &str, value: bool) {
+            if value {
+                self.flags.insert(name.to_string());
+            } else {
+                self.flags.remove(name);
+            }
+        }
+
+        fn var(&self, name: &str) -> i32 {
+            self.vars.get(name).cloned().unwrap_or(0)
+        }
+
+        fn set_var(&mut self, name: &str, value: i32) {
+            self.vars.insert(name.to_string(), value);
+        }
+
+        fn give_item(&mut self, item: &str, count: u32) {
+            self.items.push((item.to_string(), count));
+        }
+
+        fn show_text(&mut self, text: &str) {
+            self.log.push(text.to_string());
+            self.text_open = true;
+        }
+
+        fn ask(&mut self, text: &str, options: &[String]) {
+            self.log.push(format!("{} {:?}", text, options));
+            self.text_open = true;
+        }
+
+        fn take_answer(&mut self) -> Option<usize> {
+            self.answer
+        }
+
+        fn move_entity(&mut self, entity: &str, dir: Direction) -> bool {
+            if self.blocked_moves > 0 {
+                self.blocked_moves -= 1;
+                return false;
+            }
+            self.log.push(format!("{} {:?}", entity, dir));
+            true
+        }
+
+        fn warp(&mut self, area: &str, x: i32, y: i32) {
+            self.log.push(format!("warp {} {} {}", area, x, y));
+        }
+
+        fn busy(&self) -> bool {
+            self.text_open
+        }
+    }
+
+    const PROF: &str = r#"
+        # the professor
+        if flag got_starter goto after
+        text "Take one of these,\nthey're all I've got."
+        give potion 2
+        set got_starter
+        move prof east 2
+        goto end
+        label after
+        text "How's it going?"   # small talk
+        label end
+    "#;
+
+    // tick until done, closing text boxes as they show up, and return how many ticks it took
+    fn run(vm: &mut ScriptVm, host: &mut Host) -> usize {
+        let mut ticks = 0;
+        while !vm.tick(host) {
+            host.text_open = false;
+            ticks += 1;
+            assert!(ticks < 1000);
+        }
+        ticks
+    }
+
+    #[test]
+    fn parses() {
+        let script = Script::parse(PROF).unwrap();
+        assert_eq!(script.commands[0], Command::IfFlag { flag: "got_starter".to_string(), set: true, target: 6 });
+        assert_eq!(script.commands[1], Command::Text("Take one of these,\nthey're all I've got.".to_string()));
+        assert_eq!(script.commands[4], Command::Move { entity: "prof".to_string(), dir: Direction::East, steps: 2 });
+        assert_eq!(script.commands[5], Command::Goto(7));
+        assert_eq!(script.commands.len(), 7);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let err = |src: &str| Script::parse(src).unwrap_err();
+        assert_eq!(err("text \"hi\"\ngoto nowhere").line, 2);
+        assert_eq!(err("text \"unterminated").message, "unterminated string");
+        assert_eq!(err("move prof up").message, "unknown direction up");
+        assert_eq!(err("wait soon").message, "expected a number, got soon");
+        assert_eq!(err("dance").message, "bad arguments for dance");
+        assert_eq!(err("label a\nlabel a").message, "duplicate label a");
+    }
+
+    #[test]
+    fn branches_on_flags() {
+        let script = Script::parse(PROF).unwrap();
+
+        let mut host = Host::default();
+        run(&mut ScriptVm::new(script.clone()), &mut host);
+        assert_eq!(host.log, vec!["Take one of these,\nthey're all I've got.", "prof East", "prof East"]);
+        assert_eq!(host.items, vec![("potion".to_string(), 2)]);
+        assert!(host.flag("got_starter"));
+
+        host.log.clear();
+        run(&mut ScriptVm::new(script), &mut host);
+        assert_eq!(host.log, vec!["How's it going?"]);
+        assert_eq!(host.items.len(), 1);
+    }
+
+    #[test]
+    fn text_blocks_until_closed() {
+        let mut vm = ScriptVm::new(Script::parse("text \"a\"\ntext \"b\"").unwrap());
+        let mut host = Host::default();
+
+        assert!(!vm.tick(&mut host));
+        assert!(!vm.tick(&mut host));
+        assert_eq!(host.log, vec!["a"]);
+
+        host.text_open = false;
+        vm.tick(&mut host);
+        assert_eq!(host.log, vec!["a", "b"]);
+
+        host.text_open = false;
+        assert!(vm.tick(&mut host));
+    }
+
+    #[test]
+    fn waits_and_retries_moves() {
+        let mut host = Host::default();
+        let wait = run(&mut ScriptVm::new(Script::parse("wait 10").unwrap()), &mut host);
+        assert_eq!(wait, 10);
+
+        host.blocked_moves = 3;
+        let moves = run(&mut ScriptVm::new(Script::parse("move player north 2\nwarp lab 1 2").unwrap()), &mut host);
+        assert_eq!(moves, 5);
+        assert_eq!(host.log, vec!["player North", "player North", "warp lab 1 2"]);
+    }
+
+    #[test]
+    fn variables() {
+        let script = Script::parse(r#"
+            addvar talked 1
+            if var talked >= 3 goto tired
+            text "Hi!"
+            end
+            label tired
+            text "You again?"
+            setvar talked 0
+        "#).unwrap();
+        let mut host = Host::default();
+
+        for _ in 0..4 {
+            run(&mut ScriptVm::new(script.clone()), &mut host);
+        }
+        assert_eq!(host.log, vec!["Hi!", "Hi!", "You again?", "Hi!"]);
+        assert_eq!(host.var("talked"), 1);
+
+        assert_eq!(Script::parse("if var x ~ 1 goto a\nlabel a").unwrap_err().message, "unknown comparison ~");
+    }
+
+    #[test]
+    fn questions() {
+        let script = Script::parse(r#"
+            choose starter "Which one?" "BULBASAUR" "CHARMANDER" "SQUIRTLE"
+            if var starter == 1 goto fire
+            yesno sure "Really?"
+            end
+            label fire
+            text "Hot!"
+        "#).unwrap();
+
+        let mut host = Host::default();
+        host.answer = Some(1);
+        run(&mut ScriptVm::new(script.clone()), &mut host);
+        assert_eq!(host.log, vec![r#"Which one? ["BULBASAUR", "CHARMANDER", "SQUIRTLE"]"#, "Hot!"]);
+
+        // no answer picks the last option
+        let mut host = Host::default();
+        run(&mut ScriptVm::new(script), &mut host);
+        assert_eq!((host.var("starter"), host.var("sure")), (2, 1));
+
+        assert_eq!(Script::parse("choose x \"?\"").unwrap_err().message, "bad arguments for choose");
+    }
+
+    #[test]
+    fn infinite_loops_dont_hang() {
+        let mut vm = ScriptVm::new(Script::parse("label top\ngoto top").unwrap());
+        let mut host = Host::default();
+        assert!(!vm.tick(&mut host));
+        assert!(!vm.tick(&mut host));
+    }
+
+    #[test]
+    fn same_script_same_ticks() {
+        let script = Script::parse("text \"hi\"\nwait 3\nmove kid west 2\nend\ntext \"never\"").unwrap();
+        let trace = || {
+            let mut host = Host::default();
+            let mut vm = ScriptVm::new(script.clone());
+            let mut trace = vec![];
+            while !vm.tick(&mut host) {
+                host.text_open = false;
+                trace.push(host.log.len());
+            }
+            (trace, host.log)
+        };
+        assert_eq!(trace(), trace());
+        assert!(!trace().1.contains(&"never".to_string()));
+    }
+}
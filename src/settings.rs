@@ -0,0 +1,564 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use crate::input::Button;
+use crate::post::PostSettings;
+use crate::ui::{FrameStyle, FRAME_STYLES};
+use crate::view::{ScaleMode, ViewConfig};
+
+pub const MAX_SCALE: u32 = 6;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayMode {
+    Windowed,
+    Fullscreen,
+    // a window without decorations
+    Borderless,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    // vsync, always supported
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+// how big the game's screen is, before it's scaled up to the window
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenSize {
+    Gba,
+    Nds,
+    Wide,
+}
+
+impl ScreenSize {
+    pub fn view_size(self) -> [u32; 2] {
+        match self {
+            ScreenSize::Gba => ViewConfig::GBA,
+            ScreenSize::Nds => ViewConfig::NDS,
+            ScreenSize::Wide => ViewConfig::WIDE,
+        }
+    }
+}
+
+// the post-processing presets
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PostEffect {
+    Off,
+    GbaLcd,
+    Scanlines,
+    Crt,
+}
+
+impl PostEffect {
+    pub fn settings(self) -> PostSettings {
+        match self {
+            PostEffect::Off => PostSettings::default(),
+            PostEffect::GbaLcd => PostSettings::gba_lcd(),
+            PostEffect::Scanlines => PostSettings::scanlines(),
+            PostEffect::Crt => PostSettings::crt(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TextSpeed {
+    Slow,
+    Mid,
+    Fast,
+    Instant,
+}
+
+impl TextSpeed {
+    pub fn ticks_per_char(self) -> u32 {
+        match self {
+            TextSpeed::Slow => 4,
+            TextSpeed::Mid => 2,
+            TextSpeed::Fast => 1,
+            TextSpeed::Instant => 0,
+        }
+    }
+}
+
+// in percent
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(default)]
+pub struct Volumes {
+    pub master: u8,
+    pub music: u8,
+    pub sfx: u8,
+    pub cries: u8,
+}
+
+impl Default for Volumes {
+    fn default() -> Self {
+        Volumes { master: 100, music: 80, sfx: 100, cries: 100 }
+    }
+}
+
+// Everything the player can change in options, saved to settings.json in the config dir.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Settings {
+    // window size in multiples of the view
+    pub scale: u32,
+    pub screen: ScreenSize,
+    pub scale_mode: ScaleMode,
+    pub post: PostEffect,
+    pub display: DisplayMode,
+    pub present: PresentMode,
+    pub text_speed: TextSpeed,
+    pub frame: FrameStyle,
+    pub volume: Volumes,
+    // key names, e.g. "Z" or "Escape"
+    pub keys: BTreeMap<Button, Vec<String>>,
+}
+
+fn default_keys() -> BTreeMap<Button, Vec<String>> {
+    let keys: [(Button, &[&str]); 8] = [
+        (Button::Up, &["Up", "W"]),
+        (Button::Down, &["Down", "S"]),
+        (Button::Left, &["Left", "A"]),
+        (Button::Right, &["Right", "D"]),
+        (Button::A, &["Z"]),
+        (Button::B, &["X"]),
+        (Button::Start, &["Enter", "Escape"]),
+        (Button::Select, &["Backspace"]),
+    ];
+    keys.iter().map(|(b, k)| (*b, k.iter().map(|k| k.to_string()).collect())).collect()
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            scale: 3,
+            screen: ScreenSize::Gba,
+            scale_mode: ScaleMode::Integer,
+            post: PostEffect::Off,
+            display: DisplayMode::Windowed,
+            present: PresentMode::Fifo,
+            text_speed: TextSpeed::Mid,
+            frame: FrameStyle::default(),
+            volume: Volumes::default(),
+            keys: default_keys(),
+        }
+    }
+}
+
+// keys that can be bound, besides letters and digits
+const NAMED_KEYS: [&str; 14] = [
+    "Up", "Down", "Left", "Right", "Enter", "Escape", "Space", "Backspace", "Tab",
+    "LeftShift", "RightShift", "LeftControl", "RightControl", "Delete",
+];
+
+pub fn is_key_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.is_ascii_uppercase() || c.is_ascii_digit(),
+        _ => NAMED_KEYS.contains(&name),
+    }
+}
+
+fn entry<T: DeserializeOwned>(name: &str, value: Value, warnings: &mut Vec<String>) -> Option<T> {
+    match serde_json::from_value(value) {
+        Ok(v) => Some(v),
+        Err(e) => {
+            warnings.push(format!("bad {} ({}), using the default", name, e));
+            None
+        }
+    }
+}
+
+impl Settings {
+    // Never fails: anything missing, unknown or broken is left at its default, with a
+    // warning saying so.
+    pub fn from_json(json: &str) -> (Settings, Vec<String>) {
+        let mut settings = Settings::default();
+        let mut warnings = vec![];
+
+        let object = match serde_json::from_str::<Value>(json) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => {
+                warnings.push("settings aren't an object, using the defaults".to_string());
+                Map::new()
+            }
+            Err(e) => {
+                warnings.push(format!("can't read settings ({}), using the defaults", e));
+                Map::new()
+            }
+        };
+
+        for (key, value) in object {
+            match key.as_str() {
+                "scale" => match entry::<u32>("scale", value, &mut warnings) {
+                    Some(s) if s >= 1 && s <= MAX_SCALE => settings.scale = s,
+                    Some(s) => warnings.push(format!("scale {} out of range, using the default", s)),
+                    None => {}
+                },
+                "screen" => if let Some(s) = entry("screen", value, &mut warnings) { settings.screen = s },
+                "scale_mode" => if let Some(m) = entry("scale_mode", value, &mut warnings) { settings.scale_mode = m },
+                "post" => if let Some(p) = entry("post", value, &mut warnings) { settings.post = p },
+                "display" => if let Some(d) = entry("display", value, &mut warnings) { settings.display = d },
+                "present" => if let Some(p) = entry("present", value, &mut warnings) { settings.present = p },
+                "text_speed" => if let Some(t) = entry("text_speed", value, &mut warnings) { settings.text_speed = t },
+                "frame" => match entry::<FrameStyle>("frame", value, &mut warnings) {
+                    Some(f) if f.0 < FRAME_STYLES => settings.frame = f,
+                    Some(f) => warnings.push(format!("no frame style {}, using the default", f.0)),
+                    None => {}
+                },
+                "volume" => if let Some(v) = entry::<Volumes>("volume", value, &mut warnings) {
+                    settings.volume = Volumes {
+                        master: v.master.min(100),
+                        music: v.music.min(100),
+                        sfx: v.sfx.min(100),
+                        cries: v.cries.min(100),
+                    };
+                },
+                "keys" => settings.read_keys(value, &mut warnings),
+                _ => warnings.push(format!("unknown setting {}", key)),
+            }
+        }
+
+        (settings, warnings)
+    }
+
+    // one button at a time, so a typo only costs that button its custom keys
+    fn read_keys(&mut self, value: Value, warnings: &mut Vec<String>) {
+        let object = match value {
+            Value::Object(object) => object,
+            _ => {
+                warnings.push("keys aren't an object, using the defaults".to_string());
+                return;
+            }
+        };
+
+        for (button, keys) in object {
+            let b = match entry::<Button>("button", Value::String(button.clone()), warnings) {
+                Some(b) => b,
+                None => continue,
+            };
+            let keys = match entry::<Vec<String>>(&format!("keys for {}", button), keys, warnings) {
+                Some(keys) => keys,
+                None => continue,
+            };
+
+            let (good, bad): (Vec<String>, Vec<String>) = keys.into_iter().partition(|k| is_key_name(k));
+            for k in &bad {
+                warnings.push(format!("unknown key {} for {}", k, button));
+            }
+            if good.is_empty() {
+                warnings.push(format!("no usable keys for {}, using the default", button));
+            } else {
+                self.keys.insert(b, good);
+            }
+        }
+    }
+
+    pub fn view(&self) -> ViewConfig {
+        ViewConfig::new(self.screen.view_size(), self.scale_mode)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    // defaults if there's no file yet
+    pub fn load(path: &Path) -> Settings {
+        match fs::read_to_string(path) {
+            Ok(json) => {
+                let (settings, warnings) = Settings::from_json(&json);
+                for w in warnings {
+                    println!("{}: {}", path.display(), w);
+                }
+                settings
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Settings::default(),
+            Err(e) => {
+                println!("can't read {}: {}, using the default settings", path.display(), e);
+                Settings::default()
+            }
+        }
+    }
+
+    // written next to the old file and renamed over it, so a crash can't leave half a file
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, self.to_json())?;
+        fs::rename(&tmp, path)
+    }
+}
+
+// Where settings live: $XDG_CONFIG_HOME or ~/.config on Linux, %APPDATA% on Windows and
+// ~/Library/Application Support on macOS.
+pub fn config_dir() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|h| PathBuf::from(h).join("Library/Application Support"))
+    } else {
+        xdg_config_home(env::var_os("XDG_CONFIG_HOME"), env::var_os("HOME"))
+    };
+    dir.map(|d| d.join("pkmn-rs"))
+}
+
+fn xdg_config_home(xdg: Option<OsString>, home: Option<OsString>) -> Option<PathBuf> {
+    // the spec says relative paths are invalid and should be ignored
+    match xdg.map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => home.map(|h| PathBuf::from(h).join(".config")),
+    }
+}
+
+pub fn settings_path() -> PathBuf {
+    config_dir().unwrap_or_else(|| PathBuf::from(".")).join("settings.json")
+}
+
+// The options screen, in order.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OptionItem {
+    TextSpeed,
+    Frame,
+    Scale,
+    Screen,
+    ScaleMode,
+    Post,
+    Display,
+    Present,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+}
+
+pub const OPTIONS: [OptionItem; 11] = [
+    OptionItem::TextSpeed,
+    OptionItem::Frame,
+    OptionItem::Scale,
+    OptionItem::Screen,
+    OptionItem::ScaleMode,
+    OptionItem::Post,
+    OptionItem::Display,
+    OptionItem::Present,
+    OptionItem::MasterVolume,
+    OptionItem::MusicVolume,
+    OptionItem::SfxVolume,
+];
+
+fn step_volume(v: &mut u8) {
+    *v = if *v >= 100 { 0 } else { (*v / 10 + 1) * 10 };
+}
+
+impl Settings {
+    pub fn option_lines(&self) -> Vec<String> {
+        OPTIONS.iter().map(|o| match o {
+            OptionItem::TextSpeed => format!("TEXT SPEED: {}", match self.text_speed {
+                TextSpeed::Slow => "SLOW",
+                TextSpeed::Mid => "MID",
+                TextSpeed::Fast => "FAST",
+                TextSpeed::Instant => "INSTANT",
+            }),
+            OptionItem::Frame => format!("FRAME: TYPE {}", self.frame.0 + 1),
+            OptionItem::Scale => format!("SCALE: {}x", self.scale),
+            OptionItem::Screen => format!("SCREEN: {}", match self.screen {
+                ScreenSize::Gba => "GBA",
+                ScreenSize::Nds => "DS",
+                ScreenSize::Wide => "WIDE",
+            }),
+            OptionItem::ScaleMode => format!("FILTER: {}", match self.scale_mode {
+                ScaleMode::Integer => "PIXEL",
+                ScaleMode::FitNearest => "FIT",
+                ScaleMode::Stretch => "STRETCH",
+                ScaleMode::SharpBilinear => "SMOOTH",
+            }),
+            OptionItem::Post => format!("EFFECT: {}", match self.post {
+                PostEffect::Off => "OFF",
+                PostEffect::GbaLcd => "LCD",
+                PostEffect::Scanlines => "SCANLINES",
+                PostEffect::Crt => "CRT",
+            }),
+            OptionItem::Display => format!("DISPLAY: {}", match self.display {
+                DisplayMode::Windowed => "WINDOW",
+                DisplayMode::Fullscreen => "FULLSCREEN",
+                DisplayMode::Borderless => "BORDERLESS",
+            }),
+            // only picked up when the swapchain is made
+            OptionItem::Present => format!("VSYNC: {} (RESTART)", match self.present {
+                PresentMode::Fifo => "ON",
+                PresentMode::Mailbox => "TRIPLE",
+                PresentMode::Immediate => "OFF",
+            }),
+            OptionItem::MasterVolume => format!("VOLUME: {}%", self.volume.master),
+            OptionItem::MusicVolume => format!("MUSIC: {}%", self.volume.music),
+            OptionItem::SfxVolume => format!("SOUND: {}%", self.volume.sfx),
+        }).collect()
+    }
+
+    // Moves option i on to its next value, wrapping around, and says which it was.
+    pub fn cycle_option(&mut self, i: usize) -> Option<OptionItem> {
+        let item = *OPTIONS.get(i)?;
+        match item {
+            OptionItem::TextSpeed => self.text_speed = match self.text_speed {
+                TextSpeed::Slow => TextSpeed::Mid,
+                TextSpeed::Mid => TextSpeed::Fast,
+                TextSpeed::Fast => TextSpeed::Instant,
+                TextSpeed::Instant => TextSpeed::Slow,
+            },
+            OptionItem::Frame => self.frame = self.frame.next(),
+            OptionItem::Scale => self.scale = self.scale % MAX_SCALE + 1,
+            OptionItem::Screen => self.screen = match self.screen {
+                ScreenSize::Gba => ScreenSize::Nds,
+                ScreenSize::Nds => ScreenSize::Wide,
+                ScreenSize::Wide => ScreenSize::Gba,
+            },
+            OptionItem::ScaleMode => self.scale_mode = match self.scale_mode {
+                ScaleMode::Integer => ScaleMode::SharpBilinear,
+                ScaleMode::SharpBilinear => ScaleMode::FitNearest,
+                ScaleMode::FitNearest => ScaleMode::Stretch,
+                ScaleMode::Stretch => ScaleMode::Integer,
+            },
+            OptionItem::Post => self.post = match self.post {
+                PostEffect::Off => PostEffect::GbaLcd,
+                PostEffect::GbaLcd => PostEffect::Scanlines,
+                PostEffect::Scanlines => PostEffect::Crt,
+                PostEffect::Crt => PostEffect::Off,
+            },
+            OptionItem::Display => self.display = match self.display {
+                DisplayMode::Windowed => DisplayMode::Fullscreen,
+                DisplayMode::Fullscreen => DisplayMode::Borderless,
+                DisplayMode::Borderless => DisplayMode::Windowed,
+            },
+            OptionItem::Present => self.present = match self.present {
+                PresentMode::Fifo => PresentMode::Mailbox,
+                PresentMode::Mailbox => PresentMode::Immediate,
+                PresentMode::Immediate => PresentMode::Fifo,
+            },
+            OptionItem::MasterVolume => step_volume(&mut self.volume.master),
+            OptionItem::MusicVolume => step_volume(&mut self.volume.music),
+            OptionItem::SfxVolume => step_volume(&mut self.volume.sfx),
+        }
+        Some(item)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn round_trips() {
+        let mut s = Settings::default();
+        s.scale = 2;
+        s.text_speed = TextSpeed::Fast;
+        s.screen = ScreenSize::Wide;
+        s.keys.insert(Button::A, vec!["J".to_string()]);
+
+        let (back, warnings) = Settings::from_json(&s.to_json());
+        assert_eq!(back, s);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[test]
+    fn bad_entries_fall_back() {
+        let (s, warnings) = Settings::from_json(r#"{
+            "scale": 40,
+            "display": "fullscreen",
+            "text_speed": "ludicrous",
+            "volume": { "music": 250 },
+            "frame": 2,
+            "shiny_odds": 1,
+            "keys": { "a": ["K", "NotAKey"], "b": [], "turbo": ["T"] }
+        }"#);
+
+        let d = Settings::default();
+        assert_eq!(s.scale, d.scale);
+        assert_eq!(s.display, DisplayMode::Fullscreen);
+        assert_eq!(s.text_speed, d.text_speed);
+        assert_eq!(s.volume, Volumes { music: 100, ..Volumes::default() });
+        assert_eq!(s.frame, FrameStyle(2));
+        assert_eq!(s.keys[&Button::A], vec!["K"]);
+        assert_eq!(s.keys[&Button::B], d.keys[&Button::B]);
+
+        assert_eq!(warnings.len(), 6, "{:?}", warnings);
+        assert!(warnings.iter().any(|w| w == "unknown setting shiny_odds"));
+        assert!(warnings.iter().any(|w| w == "unknown key NotAKey for a"));
+    }
+
+    #[test]
+    fn garbage_is_all_defaults() {
+        for json in &["", "[1, 2]", "{ nope"] {
+            let (s, warnings) = Settings::from_json(json);
+            assert_eq!(s, Settings::default());
+            assert_eq!(warnings.len(), 1);
+        }
+    }
+
+    #[test]
+    fn key_names() {
+        assert!(is_key_name("Z") && is_key_name("7") && is_key_name("Escape"));
+        assert!(!is_key_name("z") && !is_key_name("") && !is_key_name("Hyper"));
+    }
+
+    #[test]
+    fn xdg() {
+        let path = |x: Option<&str>, h: Option<&str>| xdg_config_home(x.map(OsString::from), h.map(OsString::from));
+        assert_eq!(path(Some("/x"), Some("/home/ash")), Some(PathBuf::from("/x")));
+        assert_eq!(path(None, Some("/home/ash")), Some(PathBuf::from("/home/ash/.config")));
+        assert_eq!(path(Some("relative"), Some("/home/ash")), Some(PathBuf::from("/home/ash/.config")));
+        assert_eq!(path(None, None), None);
+    }
+
+    #[test]
+    fn options_cycle() {
+        let mut s = Settings::default();
+        assert_eq!(s.option_lines().len(), OPTIONS.len());
+        assert_eq!(s.option_lines()[0], "TEXT SPEED: MID");
+
+        assert_eq!(s.cycle_option(0), Some(OptionItem::TextSpeed));
+        assert_eq!(s.text_speed, TextSpeed::Fast);
+
+        s.scale = MAX_SCALE;
+        s.cycle_option(2);
+        assert_eq!(s.scale, 1);
+
+        assert_eq!(s.cycle_option(3), Some(OptionItem::Screen));
+        assert_eq!(s.view().view_size, ViewConfig::NDS);
+        s.cycle_option(4);
+        assert_eq!(s.view().scale_mode, ScaleMode::SharpBilinear);
+        assert!(!s.post.settings().is_active());
+        s.cycle_option(5);
+        assert_eq!(s.post.settings(), PostSettings::gba_lcd());
+
+        s.volume.music = 95;
+        s.cycle_option(9);
+        assert_eq!(s.volume.music, 100);
+        s.cycle_option(9);
+        assert_eq!(s.volume.music, 0);
+
+        assert_eq!(s.cycle_option(OPTIONS.len()), None);
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let dir = TempDir::new("settings");
+        let path = dir.0.join("nested/settings.json");
+        assert_eq!(Settings::load(&path), Settings::default());
+
+        let mut s = Settings::default();
+        s.display = DisplayMode::Borderless;
+        s.save(&path).unwrap();
+        assert_eq!(Settings::load(&path), s);
+    }
+}
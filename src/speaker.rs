@@ -0,0 +1,103 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use cpal::traits::{DeviceTrait, EventLoopTrait, HostTrait};
+use cpal::{Sample, StreamData, UnknownTypeOutputBuffer};
+use crate::audio::Mixer;
+
+// what the mixer runs at when there's no device to ask
+const NULL_RATE: u32 = 44100;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Backend {
+    // the name of the output device
+    Device(String),
+    // nothing's listening, sounds are mixed on request or not at all
+    Null,
+}
+
+// Plays a Mixer on the default output device from a thread of its own.
+pub struct Speaker {
+    mixer: Arc<Mutex<Mixer>>,
+    pub backend: Backend,
+}
+
+impl Speaker {
+    // no sound device isn't worth stopping the game over
+    pub fn open() -> Speaker {
+        match Speaker::open_device() {
+            Ok(speaker) => speaker,
+            Err(e) => {
+                println!("no sound: {}", e);
+                Speaker::null()
+            }
+        }
+    }
+
+    pub fn null() -> Speaker {
+        Speaker {
+            mixer: Arc::new(Mutex::new(Mixer::new(NULL_RATE))),
+            backend: Backend::Null,
+        }
+    }
+
+    fn open_device() -> Result<Speaker, String> {
+        let host = cpal::default_host();
+        let device = host.default_output_device().ok_or("no output device")?;
+        let name = device.name().unwrap_or_else(|_| "unknown device".to_string());
+        let format = device.default_output_format().map_err(|e| e.to_string())?;
+
+        let event_loop = host.event_loop();
+        let stream = event_loop.build_output_stream(&device, &format).map_err(|e| e.to_string())?;
+        event_loop.play_stream(stream).map_err(|e| e.to_string())?;
+
+        let mixer = Arc::new(Mutex::new(Mixer::new(format.sample_rate.0)));
+        let shared = mixer.clone();
+        let channels = format.channels as usize;
+        thread::spawn(move || {
+            let mut stereo = vec![];
+            event_loop.run(move |_, result| {
+                let buffer = match result {
+                    Ok(StreamData::Output { buffer }) => buffer,
+                    Ok(_) => return,
+                    Err(e) => {
+                        println!("sound stream error: {}", e);
+                        return;
+                    }
+                };
+
+                match buffer {
+                    UnknownTypeOutputBuffer::F32(mut out) => fill(&shared, &mut stereo, channels, &mut *out),
+                    UnknownTypeOutputBuffer::I16(mut out) => fill(&shared, &mut stereo, channels, &mut *out),
+                    UnknownTypeOutputBuffer::U16(mut out) => fill(&shared, &mut stereo, channels, &mut *out),
+                }
+            });
+        });
+
+        println!("playing sound on {} at {}Hz", name, format.sample_rate.0);
+        Ok(Speaker { mixer, backend: Backend::Device(name) })
+    }
+
+    // don't hang on to it, the sound thread needs it too
+    pub fn mixer(&self) -> MutexGuard<Mixer> {
+        self.mixer.lock().unwrap()
+    }
+}
+
+// mixes in stereo, then spreads that over however many channels the device has
+fn fill<S: Sample>(mixer: &Mutex<Mixer>, stereo: &mut Vec<f32>, channels: usize, out: &mut [S]) {
+    let channels = channels.max(1);
+    stereo.resize(out.len() / channels * 2, 0.0);
+    mixer.lock().unwrap().render(stereo);
+
+    for (frame, lr) in out.chunks_mut(channels).zip(stereo.chunks(2)) {
+        for (c, sample) in frame.iter_mut().enumerate() {
+            let value = match (channels, c) {
+                (1, _) => (lr[0] + lr[1]) / 2.0,
+                (_, 0) => lr[0],
+                (_, 1) => lr[1],
+                _ => 0.0,
+            };
+            *sample = S::from(&value);
+        }
+    }
+}
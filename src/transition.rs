@@ -0,0 +1,256 @@
+use serde::{Serialize, Deserialize};
+use image::{Rgba, RgbaImage};
+
+// how tall each of the blinds is, in pixels
+pub const BLIND_HEIGHT: f32 = 8.0;
+pub const SWIRL_ARMS: f32 = 4.0;
+// the screen breaks into two triangles per square this big
+pub const SHARD_SIZE: f32 = 16.0;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionKind {
+    // cut straight to the next scene
+    None,
+    // to black
+    Fade,
+    FadeWhite,
+    // a circle closing in on `center`
+    Iris,
+    // horizontal strips closing from the top down
+    Blinds,
+    // the battle intro, spiral arms sweeping around `center`
+    Swirl,
+    // the screen falls apart into shards, in a random order
+    Shatter,
+}
+
+// Everything the transition shader does, on the CPU. Takes the middle of a pixel,
+// `center` and `view_size` in view pixels, and returns straight alpha RGBA.
+pub fn overlay(kind: TransitionKind, coverage: f32, center: (f32, f32), view_size: (f32, f32), p: (f32, f32)) -> [f32; 4] {
+    let t = coverage.max(0.0).min(1.0);
+    let black = |covered: bool| [0.0, 0.0, 0.0, if covered { 1.0 } else { 0.0 }];
+    let d = (p.0 - center.0, p.1 - center.1);
+    let diagonal = view_size.0.hypot(view_size.1);
+
+    match kind {
+        TransitionKind::None => [0.0; 4],
+        TransitionKind::Fade => [0.0, 0.0, 0.0, t],
+        TransitionKind::FadeWhite => [1.0, 1.0, 1.0, t],
+        // hard edged like the real thing
+        TransitionKind::Iris => black(d.0.hypot(d.1) >= (1.0 - t) * diagonal),
+        TransitionKind::Blinds => black(p.1 % BLIND_HEIGHT < t * BLIND_HEIGHT),
+        TransitionKind::Swirl => {
+            let turn = d.1.atan2(d.0) / (2.0 * std::f32::consts::PI) + 0.5;
+            // the further out, the further behind, so the arms curl
+            let arm = (turn * SWIRL_ARMS + d.0.hypot(d.1) / diagonal).fract();
+            black(arm < t)
+        }
+        TransitionKind::Shatter => {
+            let cell = ((p.0 / SHARD_SIZE).floor(), (p.1 / SHARD_SIZE).floor());
+            let local = (p.0 - cell.0 * SHARD_SIZE, p.1 - cell.1 * SHARD_SIZE);
+            // every other square is cut along the other diagonal
+            let half = if (cell.0 + cell.1) as i32 % 2 == 0 {
+                local.0 + local.1 < SHARD_SIZE
+            } else {
+                local.0 < local.1
+            };
+            black(shard_hash(cell.0 as u32, cell.1 as u32, half as u32) < t)
+        }
+    }
+}
+
+// 0..1, the same in the shader
+fn shard_hash(x: u32, y: u32, z: u32) -> f32 {
+    let mut n = x.wrapping_mul(73_856_093) ^ y.wrapping_mul(19_349_663) ^ z.wrapping_mul(83_492_791);
+    n = (n ^ (n >> 13)).wrapping_mul(1_274_126_177);
+    n ^= n >> 16;
+    (n & 0xffff) as f32 / 65536.0
+}
+
+// What the transition pass draws over a view of `view_size`, with `center` as a
+// fraction of the view. For checking the shader against, and golden tests.
+pub fn reference(kind: TransitionKind, coverage: f32, center: (f32, f32), view_size: [u32; 2]) -> RgbaImage {
+    let size = (view_size[0] as f32, view_size[1] as f32);
+    let center = (center.0 * size.0, center.1 * size.1);
+    let byte = |v: f32| (v * 255.0).round() as u8;
+
+    RgbaImage::from_fn(view_size[0], view_size[1], |x, y| {
+        let c = overlay(kind, coverage, center, size, (x as f32 + 0.5, y as f32 + 0.5));
+        Rgba([byte(c[0]), byte(c[1]), byte(c[2]), byte(c[3])])
+    })
+}
+
+impl Default for TransitionKind {
+    fn default() -> Self {
+        TransitionKind::Fade
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Phase {
+    // covering the old scene
+    Out,
+    // uncovering the new one
+    In,
+    Done,
+}
+
+// Covers the screen, lets the caller switch scenes, then uncovers it again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    // as a fraction of the view, (0.5, 0.5) is the middle of the screen
+    pub center: (f32, f32),
+    phase: Phase,
+    tick: u32,
+    // ticks per phase
+    duration: u32,
+}
+
+impl Transition {
+    pub fn new(kind: TransitionKind, duration: u32, center: (f32, f32)) -> Self {
+        Transition {
+            kind,
+            center,
+            phase: Phase::Out,
+            tick: 0,
+            duration: std::cmp::max(1, duration),
+        }
+    }
+
+    // Returns true exactly once, on the tick the screen is fully covered and
+    // the scene underneath should be switched.
+    pub fn tick(&mut self) -> bool {
+        match self.phase {
+            Phase::Out => {
+                self.tick += 1;
+                if self.kind == TransitionKind::None || self.tick >= self.duration {
+                    self.tick = 0;
+                    self.phase = if self.kind == TransitionKind::None { Phase::Done } else { Phase::In };
+                    return true;
+                }
+            }
+            Phase::In => {
+                self.tick += 1;
+                if self.tick >= self.duration {
+                    self.phase = Phase::Done;
+                }
+            }
+            Phase::Done => {}
+        }
+        false
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == Phase::Done
+    }
+
+    // how much of the screen is covered, from 0 (not at all) to 1 (entirely)
+    pub fn coverage(&self) -> f32 {
+        let t = self.tick as f32 / self.duration as f32;
+        match self.phase {
+            Phase::Out => t,
+            Phase::In => 1.0 - t,
+            Phase::Done => 0.0,
+        }
+    }
+
+    // this frame of the transition, drawn on the CPU
+    pub fn reference(&self, view_size: [u32; 2]) -> RgbaImage {
+        reference(self.kind, self.coverage(), self.center, view_size)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_switches_uncovers() {
+        let mut t = Transition::new(TransitionKind::Fade, 4, (0.0, 0.0));
+        let switches: Vec<bool> = (0..10).map(|_| t.tick()).collect();
+
+        assert_eq!(switches.iter().filter(|&&s| s).count(), 1);
+        assert!(switches[3]);
+        assert!(t.is_done());
+        assert_eq!(t.coverage(), 0.0);
+    }
+
+    #[test]
+    fn coverage_peaks_at_the_switch() {
+        let mut t = Transition::new(TransitionKind::Iris, 2, (0.5, 0.5));
+        assert_eq!(t.coverage(), 0.0);
+        t.tick();
+        assert_eq!(t.coverage(), 0.5);
+        t.tick();
+        assert_eq!((t.phase(), t.coverage()), (Phase::In, 1.0));
+        t.tick();
+        assert_eq!(t.coverage(), 0.5);
+    }
+
+    const KINDS: [TransitionKind; 6] = [
+        TransitionKind::Fade, TransitionKind::FadeWhite, TransitionKind::Iris,
+        TransitionKind::Blinds, TransitionKind::Swirl, TransitionKind::Shatter,
+    ];
+
+    #[test]
+    fn clear_to_covered() {
+        for &kind in KINDS.iter() {
+            let clear = reference(kind, 0.0, (0.3, 0.6), [64, 48]);
+            assert!(clear.pixels().all(|p| p[3] == 0), "{:?}", kind);
+            let covered = reference(kind, 1.0, (0.3, 0.6), [64, 48]);
+            assert!(covered.pixels().all(|p| p[3] == 255), "{:?}", kind);
+
+            // more progress never uncovers anything
+            let (a, b) = (reference(kind, 0.4, (0.3, 0.6), [64, 48]), reference(kind, 0.6, (0.3, 0.6), [64, 48]));
+            assert!(a.pixels().zip(b.pixels()).all(|(a, b)| a[3] <= b[3]), "{:?}", kind);
+        }
+        assert!(reference(TransitionKind::None, 1.0, (0.5, 0.5), [8, 8]).pixels().all(|p| p[3] == 0));
+    }
+
+    #[test]
+    fn shapes() {
+        let view = (64.0, 48.0);
+        let iris = |p| overlay(TransitionKind::Iris, 0.6, (32.0, 24.0), view, p)[3];
+        assert_eq!((iris((32.5, 24.5)), iris((0.5, 0.5))), (0.0, 1.0));
+
+        let blinds = |y| overlay(TransitionKind::Blinds, 0.25, (0.0, 0.0), view, (0.5, y))[3];
+        assert_eq!((blinds(0.5), blinds(1.5), blinds(2.5), blinds(8.5)), (1.0, 1.0, 0.0, 1.0));
+
+        assert_eq!(overlay(TransitionKind::FadeWhite, 0.5, (0.0, 0.0), view, (0.5, 0.5)), [1.0, 1.0, 1.0, 0.5]);
+    }
+
+    // Compares each kind halfway through with res/golden. Run with UPDATE_GOLDEN=1 to
+    // write them again after changing how a transition looks.
+    #[test]
+    fn golden() {
+        let dir = std::path::Path::new(file!()).parent().unwrap().join("../res/golden");
+        let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+        for &kind in KINDS.iter() {
+            let image = reference(kind, 0.5, (0.25, 0.5), [96, 64]);
+            let name = serde_json::to_value(kind).unwrap();
+            let path = dir.join(format!("transition_{}.png", name.as_str().unwrap()));
+
+            if update {
+                image.save(&path).unwrap();
+                continue;
+            }
+            let golden = image::open(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)).to_rgba();
+            assert!(golden.dimensions() == image.dimensions() && *golden == *image, "{:?} doesn't match {}", kind, path.display());
+        }
+    }
+
+    #[test]
+    fn none_cuts() {
+        let mut t = Transition::new(TransitionKind::None, 30, (0.0, 0.0));
+        assert!(t.tick());
+        assert!(t.is_done());
+    }
+}
@@ -0,0 +1,174 @@
+use serde::{Serialize, Deserialize};
+use crate::font::GlyphQuad;
+
+// frames are drawn from res/frames.png, one 3x3 block of these per style, side by side
+pub const FRAME_TILE: u32 = 8;
+pub const FRAME_STYLES: u32 = 4;
+
+// Which frame windows are drawn with, picked by the player.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, PartialEq)]
+pub struct FrameStyle(pub u32);
+
+impl FrameStyle {
+    pub fn next(self) -> FrameStyle {
+        FrameStyle((self.0 + 1) % FRAME_STYLES)
+    }
+
+    pub fn prev(self) -> FrameStyle {
+        FrameStyle((self.0 + FRAME_STYLES - 1) % FRAME_STYLES)
+    }
+
+    // unknown styles (e.g. from an old config) get the first one
+    pub fn clamped(self) -> FrameStyle {
+        if self.0 < FRAME_STYLES { self } else { FrameStyle::default() }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UiTexture {
+    Frames,
+    // the font atlas, tinted with the quad's color
+    Font,
+}
+
+// A rectangle of one of the UI textures, in view pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UiQuad {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub u: u32,
+    pub v: u32,
+    pub texture: UiTexture,
+    pub color: [f32; 4],
+}
+
+// Everything on top of the world for one frame, drawn in order. It's in view
+// pixels, so it stays put while the camera scrolls.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UiList {
+    quads: Vec<UiQuad>,
+}
+
+impl UiList {
+    pub fn quads(&self) -> &[UiQuad] {
+        &self.quads
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quads.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.quads.clear();
+    }
+
+    // A window `width` by `height` tiles with its top left at (x, y), at least 2x2.
+    // Corners are drawn as is, and edges and the middle are repeated a tile at a time.
+    pub fn frame(&mut self, x: i32, y: i32, width: u32, height: u32, style: FrameStyle) {
+        let (width, height) = (width.max(2), height.max(2));
+        let base = style.clamped().0 * 3 * FRAME_TILE;
+
+        // which of the 3 rows or columns of the style a tile comes from
+        let part = |i: u32, n: u32| if i == 0 { 0 } else if i == n - 1 { 2 } else { 1 };
+
+        for ty in 0..height {
+            for tx in 0..width {
+                self.quads.push(UiQuad {
+                    x: x + (tx * FRAME_TILE) as i32,
+                    y: y + (ty * FRAME_TILE) as i32,
+                    width: FRAME_TILE,
+                    height: FRAME_TILE,
+                    u: base + part(tx, width) * FRAME_TILE,
+                    v: part(ty, height) * FRAME_TILE,
+                    texture: UiTexture::Frames,
+                    color: [1.0; 4],
+                });
+            }
+        }
+    }
+
+    pub fn text(&mut self, glyphs: &[GlyphQuad], pos: (i32, i32), color: [f32; 4]) {
+        self.quads.extend(glyphs.iter().map(|g| UiQuad {
+            x: pos.0 + g.x,
+            y: pos.1 + g.y,
+            width: g.width,
+            height: g.height,
+            u: g.u,
+            v: g.v,
+            texture: UiTexture::Font,
+            color,
+        }));
+    }
+}
+
+// how many tiles it takes to fit `pixels`, plus a tile of frame on both sides
+pub fn tiles_around(pixels: u32) -> u32 {
+    (pixels + FRAME_TILE - 1) / FRAME_TILE + 2
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::{BitmapFont, Spacing};
+
+    #[test]
+    fn nine_slice() {
+        let mut ui = UiList::default();
+        ui.frame(16, 8, 4, 3, FrameStyle(1));
+        let quads = ui.quads();
+        assert_eq!(quads.len(), 12);
+
+        let uv = |i: usize| (quads[i].u, quads[i].v);
+        // top left corner, a top edge, top right corner
+        assert_eq!(uv(0), (24, 0));
+        assert_eq!(uv(1), (32, 0));
+        assert_eq!(uv(2), (32, 0));
+        assert_eq!(uv(3), (40, 0));
+        // middle row, then bottom right
+        assert_eq!(uv(5), (32, 8));
+        assert_eq!(uv(11), (40, 16));
+        assert_eq!((quads[11].x, quads[11].y), (16 + 24, 8 + 16));
+    }
+
+    #[test]
+    fn tiny_frames_still_have_corners() {
+        let mut ui = UiList::default();
+        ui.frame(0, 0, 0, 1, FrameStyle::default());
+        assert_eq!(ui.quads().len(), 4);
+        assert!(ui.quads().iter().all(|q| q.u != FRAME_TILE && q.v != FRAME_TILE));
+    }
+
+    #[test]
+    fn styles_cycle() {
+        let last = FrameStyle(FRAME_STYLES - 1);
+        assert_eq!(last.next(), FrameStyle(0));
+        assert_eq!(FrameStyle(0).prev(), last);
+        assert_eq!(FrameStyle(99).clamped(), FrameStyle(0));
+    }
+
+    #[test]
+    fn tileset_fits_every_style() {
+        let frames = image::load_from_memory(include_bytes!("../res/frames.png")).unwrap().to_rgba();
+        assert_eq!(frames.dimensions(), (FRAME_STYLES * 3 * FRAME_TILE, 3 * FRAME_TILE));
+        // the middle of every window is opaque
+        for s in 0..FRAME_STYLES {
+            assert_eq!(frames.get_pixel(s * 24 + 12, 12)[3], 255);
+        }
+    }
+
+    #[test]
+    fn text_in_order_after_frames() {
+        let font = BitmapFont::builtin(Spacing::Fixed);
+        let mut ui = UiList::default();
+        ui.frame(0, 0, 2, 2, FrameStyle::default());
+        ui.text(&font.layout("hi", 100).quads, (8, 4), [1.0; 4]);
+
+        let q = ui.quads()[4];
+        assert_eq!((q.texture, q.x, q.y), (UiTexture::Font, 8, 4));
+        assert_eq!(ui.quads()[5].x, 14);
+        assert_eq!(tiles_around(20), 5);
+    }
+}
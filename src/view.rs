@@ -0,0 +1,131 @@
+use serde::{Serialize, Deserialize};
+
+pub const TILE_SIZE: u32 = 16;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleMode {
+    // largest whole multiple of the view, letterboxed
+    Integer,
+    // keep aspect ratio, nearest filtering (uneven pixels)
+    FitNearest,
+    // fill the window, ignore aspect ratio
+    Stretch,
+    // nearest to the largest whole multiple, then bilinear up to the fit size
+    SharpBilinear,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ViewConfig {
+    pub view_size: [u32; 2],
+    pub scale_mode: ScaleMode,
+}
+
+impl Default for ViewConfig {
+    fn default() -> Self {
+        ViewConfig::new(ViewConfig::GBA, ScaleMode::Integer)
+    }
+}
+
+impl ViewConfig {
+    pub const GBA: [u32; 2] = [240, 160];
+    pub const NDS: [u32; 2] = [256, 192];
+    pub const WIDE: [u32; 2] = [320, 180];
+
+    pub fn new(view_size: [u32; 2], scale_mode: ScaleMode) -> Self {
+        ViewConfig { view_size, scale_mode }
+    }
+
+    pub fn with_mode(&self, scale_mode: ScaleMode) -> Self {
+        ViewConfig { scale_mode, ..*self }
+    }
+
+    // the view plus one tile of margin for sub-tile scrolling
+    pub fn internal_size(&self) -> [u32; 2] {
+        [self.view_size[0] + TILE_SIZE, self.view_size[1] + TILE_SIZE]
+    }
+
+    // how many tiles are (at least partially) visible
+    pub fn view_tiles(&self) -> [u32; 2] {
+        [
+            (self.view_size[0] + TILE_SIZE - 1) / TILE_SIZE,
+            (self.view_size[1] + TILE_SIZE - 1) / TILE_SIZE,
+        ]
+    }
+
+    // largest whole multiple of the view that fits in the target, never less than 1
+    pub fn integer_scale(&self, target: [u32; 2]) -> u32 {
+        let x_scale = target[0] / self.view_size[0];
+        let y_scale = target[1] / self.view_size[1];
+        std::cmp::max(1, std::cmp::min(x_scale, y_scale))
+    }
+
+    // top-left and bottom-right corners of the view on the target, as blit offsets
+    pub fn dest_rect(&self, target: [u32; 2]) -> ([i32; 3], [i32; 3]) {
+        let (w1, h1) = (self.view_size[0] as i32, self.view_size[1] as i32);
+        let (w2, h2) = (target[0] as i32, target[1] as i32);
+
+        let (ws, hs) = match self.scale_mode {
+            ScaleMode::Integer => {
+                let scale = self.integer_scale(target) as i32;
+                (w1*scale, h1*scale)
+            }
+            ScaleMode::FitNearest | ScaleMode::SharpBilinear => {
+                // compare w2/w1 against h2/h1 without going through floats
+                if w2 * h1 <= h2 * w1 {
+                    (w2, w2 * h1 / w1)
+                } else {
+                    (h2 * w1 / h1, h2)
+                }
+            }
+            ScaleMode::Stretch => (w2, h2),
+        };
+
+        let x_offset = (w2-ws)/2;
+        let y_offset = (h2-hs)/2;
+
+        ([x_offset, y_offset, 0], [x_offset+ws, y_offset+hs, 1])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_size_has_a_tile_of_margin() {
+        let cfg = ViewConfig::new(ViewConfig::WIDE, ScaleMode::Integer);
+        assert_eq!(cfg.internal_size(), [336, 196]);
+        assert_eq!(cfg.view_tiles(), [20, 12]);
+        assert_eq!(ViewConfig::default().internal_size(), [256, 176]);
+    }
+
+    #[test]
+    fn integer_letterboxes() {
+        let cfg = ViewConfig::new(ViewConfig::GBA, ScaleMode::Integer);
+        assert_eq!(cfg.dest_rect([800, 600]), ([40, 60, 0], [760, 540, 1]));
+        assert_eq!(cfg.dest_rect([240, 160]), ([0, 0, 0], [240, 160, 1]));
+    }
+
+    #[test]
+    fn fit_keeps_aspect() {
+        let cfg = ViewConfig::new(ViewConfig::GBA, ScaleMode::FitNearest);
+        assert_eq!(cfg.dest_rect([800, 600]), ([0, 33, 0], [800, 566, 1]));
+        assert_eq!(cfg.dest_rect([1200, 600]), ([150, 0, 0], [1050, 600, 1]));
+    }
+
+    #[test]
+    fn stretch_fills() {
+        let cfg = ViewConfig::new(ViewConfig::NDS, ScaleMode::Stretch);
+        assert_eq!(cfg.dest_rect([800, 600]), ([0, 0, 0], [800, 600, 1]));
+    }
+
+    #[test]
+    fn sharp_bilinear_prescales_by_whole_multiples() {
+        let cfg = ViewConfig::new(ViewConfig::GBA, ScaleMode::SharpBilinear);
+        assert_eq!(cfg.integer_scale([800, 600]), 3);
+        assert_eq!(cfg.integer_scale([100, 100]), 1);
+        assert_eq!(cfg.dest_rect([800, 600]), cfg.with_mode(ScaleMode::FitNearest).dest_rect([800, 600]));
+    }
+}
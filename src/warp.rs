@@ -0,0 +1,103 @@
+use serde::{Serialize, Deserialize};
+use crate::area::Direction;
+use crate::transition::TransitionKind;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct TileRect {
+    pub x: i32,
+    pub y: i32,
+    #[serde(default = "one")]
+    pub width: u32,
+    #[serde(default = "one")]
+    pub height: u32,
+}
+
+fn one() -> u32 { 1 }
+
+impl TileRect {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width as i32 && y < self.y + self.height as i32
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum WarpTrigger {
+    // walking onto the tile, e.g. stairs or a cave entrance
+    Step,
+    // walking into the tile, e.g. a door in a wall or the exit mat of a house
+    Face,
+}
+
+impl Default for WarpTrigger {
+    fn default() -> Self {
+        WarpTrigger::Step
+    }
+}
+
+// Moves the player to another area, independent of the NeighborArea links.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Warp {
+    pub rect: TileRect,
+    pub area: String,
+    pub x: i32,
+    pub y: i32,
+    // which way the player faces after warping, keeps the current facing if unset
+    #[serde(default)]
+    pub facing: Option<Direction>,
+    #[serde(default)]
+    pub trigger: WarpTrigger,
+    #[serde(default)]
+    pub transition: TransitionKind,
+}
+
+impl Warp {
+    // `pos` is where the player is standing, `moved` is whether they just stepped there
+    // (as opposed to bumping into something while facing `facing`)
+    pub fn triggered(&self, pos: (i32, i32), facing: Direction, moved: bool) -> bool {
+        match self.trigger {
+            WarpTrigger::Step => moved && self.rect.contains(pos.0, pos.1),
+            WarpTrigger::Face => {
+                let d = facing.delta();
+                !moved && self.rect.contains(pos.0 + d.0, pos.1 + d.1)
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warp(json: &str) -> Warp {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn defaults() {
+        let w = warp(r#"{ "rect": { "x": 1, "y": 2 }, "area": "house", "x": 3, "y": 7 }"#);
+        assert_eq!(w.rect, TileRect { x: 1, y: 2, width: 1, height: 1 });
+        assert_eq!(w.trigger, WarpTrigger::Step);
+        assert_eq!(w.transition, TransitionKind::Fade);
+        assert_eq!(w.facing, None);
+    }
+
+    #[test]
+    fn step_trigger() {
+        let w = warp(r#"{ "rect": { "x": 1, "y": 2, "width": 2 }, "area": "cave", "x": 0, "y": 0 }"#);
+        assert!(w.triggered((2, 2), Direction::East, true));
+        assert!(!w.triggered((2, 2), Direction::East, false));
+        assert!(!w.triggered((3, 2), Direction::West, true));
+    }
+
+    #[test]
+    fn face_trigger() {
+        let w = warp(r#"{ "rect": { "x": 4, "y": 4 }, "area": "house", "x": 3, "y": 7,
+                          "facing": "north", "trigger": "face", "transition": "iris" }"#);
+        assert!(w.triggered((4, 5), Direction::North, false));
+        assert!(!w.triggered((4, 5), Direction::South, false));
+        assert!(!w.triggered((4, 4), Direction::North, true));
+        assert_eq!(w.transition, TransitionKind::Iris);
+    }
+}
@@ -7,10 +7,45 @@ use vulkano::instance::Instance;
 use vulkano::swapchain::Surface;
 use image::{DynamicImage, imageops};
 use std::ops::Deref;
+use std::collections::{BTreeMap, HashMap};
+use crate::input::{Button, Input};
+use crate::settings::DisplayMode;
 
 pub struct WindowThing {
     pub events: Receiver<(f64, WindowEvent)>,
     pub surface: Arc<Surface<WrappedWindow>>,
+    pub input: Input,
+    bindings: HashMap<Key, Button>,
+}
+
+// the names settings::is_key_name accepts
+fn key(name: &str) -> Option<Key> {
+    let key = match name {
+        "Up" => Key::Up,
+        "Down" => Key::Down,
+        "Left" => Key::Left,
+        "Right" => Key::Right,
+        "Enter" => Key::Enter,
+        "Escape" => Key::Escape,
+        "Space" => Key::Space,
+        "Backspace" => Key::Backspace,
+        "Tab" => Key::Tab,
+        "LeftShift" => Key::LeftShift,
+        "RightShift" => Key::RightShift,
+        "LeftControl" => Key::LeftControl,
+        "RightControl" => Key::RightControl,
+        "Delete" => Key::Delete,
+        "A" => Key::A, "B" => Key::B, "C" => Key::C, "D" => Key::D, "E" => Key::E,
+        "F" => Key::F, "G" => Key::G, "H" => Key::H, "I" => Key::I, "J" => Key::J,
+        "K" => Key::K, "L" => Key::L, "M" => Key::M, "N" => Key::N, "O" => Key::O,
+        "P" => Key::P, "Q" => Key::Q, "R" => Key::R, "S" => Key::S, "T" => Key::T,
+        "U" => Key::U, "V" => Key::V, "W" => Key::W, "X" => Key::X, "Y" => Key::Y,
+        "Z" => Key::Z,
+        "0" => Key::Num0, "1" => Key::Num1, "2" => Key::Num2, "3" => Key::Num3, "4" => Key::Num4,
+        "5" => Key::Num5, "6" => Key::Num6, "7" => Key::Num7, "8" => Key::Num8, "9" => Key::Num9,
+        _ => return None,
+    };
+    Some(key)
 }
 
 impl WindowThing {
@@ -40,7 +75,6 @@ impl WindowThing {
             mut_window.set_key_polling(true);
             mut_window.set_scroll_polling(true);
             mut_window.set_char_polling(true);
-            mut_window.set_size_limits(dimensions[0], dimensions[1], std::u32::MAX, std::u32::MAX);
 
             if let DynamicImage::ImageRgba8(icon) = image::open("res/master16.png").unwrap() {
                 //Set the icon to be multiple sizes of the same icon to account for scaling
@@ -52,75 +86,106 @@ impl WindowThing {
             }
         }
 
-        WindowThing {
+        let window_thing = WindowThing {
             events,
             surface,
+            input: Input::default(),
+            bindings: HashMap::new(),
+        };
+        window_thing.set_view_size(dimensions);
+        window_thing
+    }
+
+    // the window can't be made smaller than one pixel per view pixel
+    pub fn set_view_size(&self, view_size: [u32; 2]) {
+        let mut window = self.surface.window().write().unwrap();
+        window.set_size_limits(view_size[0], view_size[1], std::u32::MAX, std::u32::MAX);
+
+        let (w, h) = window.get_size();
+        if (w as u32) < view_size[0] || (h as u32) < view_size[1] {
+            window.set_size(view_size[0] as i32, view_size[1] as i32);
         }
     }
 
-    pub fn handle_input(&self) {
-        self.surface.window().poll_events();
-        let events = &self.events;
-        for (_, event) in glfw::flush_messages(&events) {
-            match event {
-                glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
-                    let mut window = self.surface.window().write().unwrap();
-                    window.set_should_close(true);
-                }
-                glfw::WindowEvent::Key(Key::Right, _, Action::Press, _) => {
-                    // println!("right was pressed");
-                    let mut window = self.surface.window().write().unwrap();
-                    let (w, h) = window.get_size();
-                    window.set_size(w * 2, h * 2);
-                }
-                glfw::WindowEvent::Key(Key::Left, _, Action::Press, _) => {
-                    // println!("left was pressed");
-                    let mut window = self.surface.window().write().unwrap();
-                    let (w, h) = window.get_size();
-                    window.set_size(w / 2, h / 2);
+    // quitting goes through the pause menu, so this is the only way out
+    pub fn close(&self) {
+        self.surface.window().write().unwrap().set_should_close(true);
+    }
+
+    // a key bound to more than one button goes to whichever comes last
+    pub fn set_bindings(&mut self, keys: &BTreeMap<Button, Vec<String>>) {
+        self.bindings.clear();
+        for (&button, names) in keys {
+            for name in names {
+                match key(name) {
+                    Some(k) => { self.bindings.insert(k, button); }
+                    None => println!("can't bind unknown key {}", name),
                 }
-                glfw::WindowEvent::Key(Key::Enter, _, Action::Press, _) => {
-                    // println!("enter was pressed");
-                    let window = self.surface.window();
-                    if window.is_fullscreen() {
-                        window.exit_fullscreen()
-                    } else {
-                        window.enter_fullscreen()
-                    }
+            }
+        }
+    }
+
+    pub fn set_scale(&self, view_size: [u32; 2], scale: u32) {
+        let window = self.surface.window();
+        if window.is_fullscreen() {
+            return;
+        }
+        window.write().unwrap().set_size((view_size[0] * scale) as i32, (view_size[1] * scale) as i32);
+    }
+
+    pub fn set_display(&self, mode: DisplayMode) {
+        let window = self.surface.window();
+
+        match mode {
+            DisplayMode::Fullscreen => window.enter_fullscreen(),
+            DisplayMode::Windowed => {
+                window.exit_fullscreen();
+                if window.read().unwrap().is_decorated() {
+                    return;
                 }
-                glfw::WindowEvent::Key(Key::Down, _, Action::Press, _) => {
-                    // println!("enter was pressed");
-                    let mut window = self.surface.window().write().unwrap();
-                    let (w, h) = window.get_size();
-                    window.set_decorated(false);
-                    window.set_size(w, h);
-                    let (l,u,_,_) = window.get_frame_size();
-                    let (x, y) = window.get_pos();
-                    window.set_pos(x+l, y+u);
+
+                // putting the decorations back only takes after hiding the window
+                window.save_attributes();
+                {
+                    let mut win = window.write().unwrap();
+                    win.set_decorated(true);
+                    win.set_focus_on_show(false);
+                    win.hide();
                 }
-                glfw::WindowEvent::Key(Key::Up, _, Action::Press, _) => {
-                    // println!("enter was pressed");
-                    let window = self.surface.window();
 
-                    if window.is_fullscreen() { return }
+                window.load_attributes();
+                window.write().unwrap().show();
+            }
+            DisplayMode::Borderless => {
+                window.exit_fullscreen();
+                let mut window = window.write().unwrap();
+                if !window.is_decorated() {
+                    return;
+                }
 
-                    {
-                        let window = window.read().unwrap();
-                        if window.is_decorated() { return }
-                    }
+                // keep the contents where they were
+                let (w, h) = window.get_size();
+                window.set_decorated(false);
+                window.set_size(w, h);
+                let (l, u, _, _) = window.get_frame_size();
+                let (x, y) = window.get_pos();
+                window.set_pos(x + l, y + u);
+            }
+        }
+    }
 
-                    window.save_attributes();
-                    {
-                        let mut win = window.write().unwrap();
-                        win.set_decorated(true);
-                        win.set_focus_on_show(false);
-                        win.hide();
+    pub fn handle_input(&mut self) {
+        self.surface.window().poll_events();
+        for (_, event) in glfw::flush_messages(&self.events) {
+            match event {
+                glfw::WindowEvent::Key(key, _, Action::Press, _) => {
+                    if let Some(&button) = self.bindings.get(&key) {
+                        self.input.press(button);
                     }
-
-                    window.load_attributes();
-                    {
-                        let mut win = window.write().unwrap();
-                        win.show();
+                }
+                glfw::WindowEvent::Key(key, _, Action::Release, _) => {
+                    if let Some(&button) = self.bindings.get(&key) {
+                        self.input.release(button);
                     }
                 }
                 _ => {}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::area::{Area, Direction};
+use crate::layout::WorldLayout;
+use crate::warp::Warp;
+
+// How far past the current area the joined map reaches, so even the widest view
+// centered on the player at the area's edge doesn't run off it.
+pub const JOIN_MARGIN: i32 = 16;
+
+// The player moved into a different area. Adding `delta` to a position in the old
+// area's coordinates gives the same tile in the new area's coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crossing {
+    pub from: String,
+    pub to: String,
+    pub delta: (i32, i32),
+}
+
+// Keeps track of which area the player is in, and the joined map around it.
+pub struct World {
+    areas: HashMap<String, Rc<Area>>,
+    current: Rc<Area>,
+    // everything reachable from the current area, with the current area's (0, 0) at the global (0, 0)
+    layout: WorldLayout,
+    joined: Area,
+    // where the current area's (0, 0) sits in the joined map
+    origin: (i32, i32),
+}
+
+impl World {
+    pub fn new(areas: HashMap<String, Rc<Area>>, start: &str) -> Option<Self> {
+        let current = areas.get(start)?.clone();
+        let (layout, joined, origin) = World::join(&areas, &current);
+
+        Some(World { areas, current, layout, joined, origin })
+    }
+
+    fn join(areas: &HashMap<String, Rc<Area>>, center: &Area) -> (WorldLayout, Area, (i32, i32)) {
+        let layout = WorldLayout::build(areas, &center.name).unwrap();
+
+        for issue in layout.issues() {
+            println!("{}: {}", center.name, issue);
+        }
+
+        let joined = layout.window(
+            -JOIN_MARGIN,
+            -JOIN_MARGIN,
+            center.width + 2 * JOIN_MARGIN as usize,
+            center.height + 2 * JOIN_MARGIN as usize,
+        );
+        (layout, joined, (JOIN_MARGIN, JOIN_MARGIN))
+    }
+
+    pub fn area(&self, name: &str) -> Option<&Rc<Area>> {
+        self.areas.get(name)
+    }
+
+    pub fn current(&self) -> &Rc<Area> {
+        &self.current
+    }
+
+    pub fn joined(&self) -> &Area {
+        &self.joined
+    }
+
+    pub fn origin(&self) -> (i32, i32) {
+        self.origin
+    }
+
+    // current area coordinates -> joined map coordinates, e.g. for the camera
+    pub fn to_joined(&self, pos: (i32, i32)) -> (i32, i32) {
+        (pos.0 + self.origin.0, pos.1 + self.origin.1)
+    }
+
+    // Called whenever the player's position (in current area coordinates) changes.
+    // When it's left the current area for a neighbor, that neighbor becomes the
+    // center of the joined map and the caller should rebase its coordinates by the delta.
+    pub fn update_position(&mut self, pos: (i32, i32)) -> Option<Crossing> {
+        if self.current.contains(pos.0, pos.1) {
+            return None;
+        }
+
+        // the layout's global frame is the current area's, so this is a position in `next`
+        let (next, local) = self.layout.area_at(pos.0, pos.1)?;
+        let next = next.clone();
+        let crossing = Crossing {
+            from: self.current.name.clone(),
+            to: next.name.clone(),
+            delta: (local.0 - pos.0, local.1 - pos.1),
+        };
+
+        self.enter(next);
+        Some(crossing)
+    }
+
+    pub fn find_warp(&self, pos: (i32, i32), facing: Direction, moved: bool) -> Option<&Warp> {
+        self.current.warps.iter().find(|w| w.triggered(pos, facing, moved))
+    }
+
+    // Make an area current without a shared border, e.g. after a warp.
+    pub fn set_current(&mut self, name: &str) -> bool {
+        match self.areas.get(name).cloned() {
+            Some(area) => {
+                self.enter(area);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn enter(&mut self, area: Rc<Area>) {
+        let (layout, joined, origin) = World::join(&self.areas, &area);
+        self.current = area;
+        self.layout = layout;
+        self.joined = joined;
+        self.origin = origin;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 3x3 center with a neighbor on every side, some of them offset
+    fn world() -> World {
+        let mut areas = HashMap::new();
+        let mut add = |json: &str| {
+            let area: Area = serde_json::from_str(json).unwrap();
+            areas.insert(area.name.clone(), Rc::new(area));
+        };
+
+        add(r#"{
+            "north": { "offset": 1, "area": "up" },
+            "south": { "offset": -2, "area": "down" },
+            "east": { "offset": -1, "area": "right" },
+            "west": { "offset": 0, "area": "left" },
+            "tilemap": { "width": 3, "height": 3, "map": [1, 1, 1, 1, 1, 1, 1, 1, 1] },
+            "name": "center"
+        }"#);
+        add(r#"{
+            "south": { "offset": -1, "area": "center" },
+            "tilemap": { "width": 2, "height": 2, "map": [2, 2, 2, 2] },
+            "name": "up"
+        }"#);
+        add(r#"{
+            "north": { "offset": 2, "area": "center" },
+            "tilemap": { "width": 4, "height": 2, "map": [3, 3, 3, 3, 3, 3, 3, 3] },
+            "name": "down"
+        }"#);
+        add(r#"{
+            "west": { "offset": 1, "area": "center" },
+            "tilemap": { "width": 2, "height": 4, "map": [4, 4, 4, 4, 4, 4, 4, 4] },
+            "name": "right"
+        }"#);
+        add(r#"{
+            "east": { "offset": 0, "area": "center" },
+            "tilemap": { "width": 1, "height": 3, "map": [5, 5, 5] },
+            "name": "left"
+        }"#);
+
+        World::new(areas, "center").unwrap()
+    }
+
+    // walk one step, rebasing the position like the player controller would
+    fn step(world: &mut World, pos: (i32, i32), dir: Direction) -> ((i32, i32), Option<Crossing>) {
+        let d = dir.delta();
+        let pos = (pos.0 + d.0, pos.1 + d.1);
+        match world.update_position(pos) {
+            Some(c) => ((pos.0 + c.delta.0, pos.1 + c.delta.1), Some(c)),
+            None => (pos, None),
+        }
+    }
+
+    fn tile(world: &World, pos: (i32, i32)) -> u32 {
+        let (x, y) = world.to_joined(pos);
+        let joined = world.joined();
+        joined.map[y as usize * joined.width + x as usize]
+    }
+
+    #[test]
+    fn stays_inside() {
+        let mut w = world();
+        assert_eq!(step(&mut w, (1, 1), Direction::East), ((2, 1), None));
+        assert_eq!(w.current().name, "center");
+    }
+
+    #[test]
+    fn cross_north() {
+        let mut w = world();
+        let (pos, c) = step(&mut w, (1, 0), Direction::North);
+        assert_eq!(c.unwrap().to, "up");
+        assert_eq!(pos, (0, 1));
+        assert_eq!(tile(&w, pos), 2);
+    }
+
+    #[test]
+    fn cross_south_negative_offset() {
+        let mut w = world();
+        // "down" starts two tiles left of the center
+        let (pos, c) = step(&mut w, (0, 2), Direction::South);
+        assert_eq!(c.unwrap(), Crossing { from: "center".to_string(), to: "down".to_string(), delta: (2, -3) });
+        assert_eq!(pos, (2, 0));
+        assert_eq!(tile(&w, pos), 3);
+    }
+
+    #[test]
+    fn cross_east_negative_offset() {
+        let mut w = world();
+        let (pos, c) = step(&mut w, (2, 0), Direction::East);
+        assert_eq!(c.unwrap().to, "right");
+        assert_eq!(pos, (0, 1));
+        assert_eq!(tile(&w, pos), 4);
+    }
+
+    #[test]
+    fn cross_west_and_back() {
+        let mut w = world();
+        let (pos, c) = step(&mut w, (0, 2), Direction::West);
+        assert_eq!(c.unwrap().to, "left");
+        assert_eq!(pos, (0, 2));
+        assert_eq!(tile(&w, pos), 5);
+
+        let (pos, c) = step(&mut w, pos, Direction::East);
+        assert_eq!(c.unwrap().to, "center");
+        assert_eq!(pos, (0, 2));
+        assert_eq!(w.current().name, "center");
+    }
+
+    #[test]
+    fn void_doesnt_cross() {
+        let mut w = world();
+        // north of the center's first column is outside of "up"
+        assert_eq!(step(&mut w, (0, 0), Direction::North), ((0, -1), None));
+        assert_eq!(w.current().name, "center");
+    }
+
+    #[test]
+    fn camera_doesnt_jump() {
+        let mut w = world();
+
+        // everything the camera sees, in joined map tiles
+        let view = |w: &World, camera: (i32, i32)| -> Vec<u32> {
+            let joined = w.joined();
+            let mut tiles = vec![];
+            for y in 0..4 {
+                for x in 0..3 {
+                    let (jx, jy) = w.to_joined((camera.0 + x, camera.1 + y));
+                    tiles.push(if joined.contains(jx, jy) { joined.map[jy as usize * joined.width + jx as usize] } else { 0 });
+                }
+            }
+            tiles
+        };
+
+        let camera = (0, -2);
+        let before = view(&w, camera);
+
+        let c = w.update_position((1, -1)).unwrap();
+        let camera = (camera.0 + c.delta.0, camera.1 + c.delta.1);
+
+        // same tiles, even though the joined map was rebuilt around "up"
+        assert_eq!(w.current().name, "up");
+        assert_eq!(view(&w, camera), before);
+    }
+
+    #[test]
+    fn joins_areas_past_the_neighbors() {
+        let mut areas = HashMap::new();
+        for json in [
+            r#"{ "north": { "offset": 0, "area": "up" }, "east": { "offset": 0, "area": "right" },
+                 "tilemap": { "width": 2, "height": 2, "map": [1, 1, 1, 1] }, "name": "center" }"#,
+            r#"{ "south": { "offset": 0, "area": "center" }, "east": { "offset": 0, "area": "corner" },
+                 "tilemap": { "width": 2, "height": 1, "map": [2, 2] }, "name": "up" }"#,
+            r#"{ "west": { "offset": 0, "area": "center" },
+                 "tilemap": { "width": 1, "height": 2, "map": [3, 3] }, "name": "right" }"#,
+            r#"{ "east": { "offset": 0, "area": "far" },
+                 "tilemap": { "width": 1, "height": 1, "map": [4] }, "name": "corner" }"#,
+            r#"{ "tilemap": { "width": 1, "height": 1, "map": [5] }, "name": "far" }"#,
+        ].iter() {
+            let area: Area = serde_json::from_str(json).unwrap();
+            areas.insert(area.name.clone(), Rc::new(area));
+        }
+        let w = World::new(areas, "center").unwrap();
+
+        // the corner is only linked from "up", and "far" is two hops out
+        assert_eq!(tile(&w, (2, -1)), 4);
+        assert_eq!(tile(&w, (3, -1)), 5);
+        assert_eq!(tile(&w, (3, 0)), 0);
+        assert_eq!(w.origin(), (JOIN_MARGIN, JOIN_MARGIN));
+    }
+}